@@ -0,0 +1,9 @@
+-- Keep generated posts around until the user approves or discards them
+CREATE TABLE IF NOT EXISTS pending_drafts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    telegram_id INTEGER NOT NULL,
+    transcript TEXT NOT NULL,
+    content TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    FOREIGN KEY (telegram_id) REFERENCES users(telegram_id)
+);
@@ -0,0 +1,2 @@
+-- Remember which bot message shows the draft so replies to it can edit the draft
+ALTER TABLE pending_drafts ADD COLUMN message_id INTEGER;
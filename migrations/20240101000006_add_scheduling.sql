@@ -0,0 +1,4 @@
+-- Default Typefully schedule per user and per-draft overrides
+ALTER TABLE users ADD COLUMN default_schedule TEXT;
+ALTER TABLE users ADD COLUMN utc_offset_minutes INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE pending_drafts ADD COLUMN schedule TEXT;
@@ -0,0 +1,2 @@
+-- Add thread mode setting to users table
+ALTER TABLE users ADD COLUMN thread_enabled BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,3 @@
+-- Let users publish to other destinations than Typefully
+ALTER TABLE users ADD COLUMN destination TEXT NOT NULL DEFAULT 'typefully';
+ALTER TABLE users ADD COLUMN webhook_url TEXT;
@@ -0,0 +1,2 @@
+-- Distinguish usage from voice notes and typed text
+ALTER TABLE voice_note_usage ADD COLUMN kind TEXT NOT NULL DEFAULT 'voice';
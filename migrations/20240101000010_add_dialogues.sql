@@ -0,0 +1,6 @@
+-- Persist dialogue state so restarts don't interrupt conversations
+CREATE TABLE IF NOT EXISTS dialogues (
+    chat_id INTEGER PRIMARY KEY,
+    state TEXT NOT NULL,
+    updated_at DATETIME NOT NULL
+);
@@ -0,0 +1,15 @@
+-- Replace the rewrite toggle with named style profiles
+CREATE TABLE IF NOT EXISTS style_profiles (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    telegram_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    instructions TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL,
+    UNIQUE (telegram_id, name),
+    FOREIGN KEY (telegram_id) REFERENCES users(telegram_id)
+);
+
+ALTER TABLE users ADD COLUMN style_profile TEXT NOT NULL DEFAULT 'rewrite';
+UPDATE users SET style_profile = 'format' WHERE rewrite_enabled = FALSE;
+ALTER TABLE users DROP COLUMN rewrite_enabled;
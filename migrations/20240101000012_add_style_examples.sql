@@ -0,0 +1,10 @@
+-- Store example posts that show the model how a user writes
+CREATE TABLE IF NOT EXISTS style_examples (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    telegram_id INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    source TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    UNIQUE (telegram_id, content),
+    FOREIGN KEY (telegram_id) REFERENCES users(telegram_id)
+);
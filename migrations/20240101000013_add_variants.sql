@@ -0,0 +1,3 @@
+-- Let users pick one of several generated variants
+ALTER TABLE users ADD COLUMN variant_count INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE pending_drafts ADD COLUMN variant_group INTEGER;
@@ -0,0 +1,2 @@
+-- Let users with their own key use an OpenAI-compatible server
+ALTER TABLE users ADD COLUMN ai_base_url TEXT;
@@ -0,0 +1,3 @@
+-- Let users choose the models used for their notes
+ALTER TABLE users ADD COLUMN chat_model TEXT;
+ALTER TABLE users ADD COLUMN transcription_model TEXT;
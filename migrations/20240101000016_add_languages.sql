@@ -0,0 +1,4 @@
+-- Language hint for transcription, target language for posts and the detected language of drafts
+ALTER TABLE users ADD COLUMN transcription_language TEXT;
+ALTER TABLE users ADD COLUMN output_language TEXT;
+ALTER TABLE pending_drafts ADD COLUMN language TEXT;
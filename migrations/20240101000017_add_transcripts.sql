@@ -0,0 +1,35 @@
+-- Keep every transcript and its post, once written, so users can browse and search them later
+CREATE TABLE IF NOT EXISTS transcripts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    telegram_id INTEGER NOT NULL,
+    transcript TEXT NOT NULL,
+    post TEXT,
+    created_at DATETIME NOT NULL,
+    FOREIGN KEY (telegram_id) REFERENCES users(telegram_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_transcripts_telegram_id ON transcripts(telegram_id, created_at);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS transcripts_fts USING fts5(
+    transcript,
+    post,
+    content = 'transcripts',
+    content_rowid = 'id'
+);
+
+CREATE TRIGGER IF NOT EXISTS transcripts_ai AFTER INSERT ON transcripts BEGIN
+    INSERT INTO transcripts_fts (rowid, transcript, post) VALUES (new.id, new.transcript, new.post);
+END;
+
+CREATE TRIGGER IF NOT EXISTS transcripts_ad AFTER DELETE ON transcripts BEGIN
+    INSERT INTO transcripts_fts (transcripts_fts, rowid, transcript, post)
+    VALUES ('delete', old.id, old.transcript, old.post);
+END;
+
+CREATE TRIGGER IF NOT EXISTS transcripts_au AFTER UPDATE ON transcripts BEGIN
+    INSERT INTO transcripts_fts (transcripts_fts, rowid, transcript, post)
+    VALUES ('delete', old.id, old.transcript, old.post);
+    INSERT INTO transcripts_fts (rowid, transcript, post) VALUES (new.id, new.transcript, new.post);
+END;
+
+ALTER TABLE pending_drafts ADD COLUMN transcript_id INTEGER REFERENCES transcripts(id) ON DELETE SET NULL;
@@ -0,0 +1,15 @@
+-- Transcripts captured as ideas instead of being turned into drafts right away
+CREATE TABLE IF NOT EXISTS ideas (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    telegram_id INTEGER NOT NULL,
+    transcript TEXT NOT NULL,
+    language TEXT,
+    tags TEXT NOT NULL DEFAULT '',
+    created_at DATETIME NOT NULL,
+    transcript_id INTEGER REFERENCES transcripts(id) ON DELETE SET NULL,
+    FOREIGN KEY (telegram_id) REFERENCES users(telegram_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_ideas_telegram_id ON ideas(telegram_id, created_at);
+
+ALTER TABLE users ADD COLUMN inbox_enabled BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,5 @@
+-- Remember where sent posts ended up in Typefully and whether to create share links
+ALTER TABLE transcripts ADD COLUMN typefully_draft_id INTEGER;
+ALTER TABLE transcripts ADD COLUMN typefully_url TEXT;
+ALTER TABLE transcripts ADD COLUMN typefully_share_url TEXT;
+ALTER TABLE users ADD COLUMN share_enabled BOOLEAN NOT NULL DEFAULT FALSE;
@@ -13,6 +13,7 @@ if [ ! -f .env ]; then
 # Required
 TELOXIDE_TOKEN=your_telegram_bot_token
 OPENAI_API_KEY=your_openai_api_key
+ENCRYPTION_KEY=$(openssl rand -base64 32)
 
 # Optional
 RUST_LOG=info
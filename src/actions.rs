@@ -1,23 +1,54 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use log::{error, info};
-use serde_json::json;
 use teloxide::{
     net::Download,
     prelude::*,
-    types::{InlineQueryResultArticle, InputMessageContent, InputMessageContentText},
+    types::{
+        InlineKeyboardMarkup, InlineQueryResultArticle, InputMessageContent,
+        InputMessageContentText, MessageId, ReplyParameters,
+    },
     utils::command::BotCommands,
 };
+use time::{macros::format_description, OffsetDateTime};
 
 use crate::{
-    ai::{make_summary, transcribe_voice_note},
-    commands::{keyboard, BotCommand, BotDialogue, State},
-    db::{Database, User, FREE_USAGE_LIMIT_SECONDS},
+    ai::{
+        make_summary, make_thread, revise_summary, stitch_transcripts, KeyValidationError,
+        OpenAiApi, WritingOptions, FEW_SHOT_EXAMPLES,
+    },
+    commands::{
+        examples_keyboard, history_keyboard, ideas_keyboard, keyboard, published_keyboard,
+        review_keyboard, search_keyboard, variant_keyboard, BotCommand, BotDialogue, DraftAction,
+        ExampleAction, HistoryAction, IdeaAction, State,
+    },
+    crypto::mask_secret,
+    db::{
+        ArchivedTranscript, Database, Idea, PendingDraft, StyleExample, User,
+        FREE_USAGE_LIMIT_SECONDS, HISTORY_PAGE_SIZE, MAX_STYLE_EXAMPLES, MAX_VARIANTS,
+    },
+    ideas,
+    language::{language_name, parse_language},
+    media::{extract_audio, split_for_upload, Media, TooLargeToDownload},
+    progress::{Progress, Stage},
+    providers::{ModelRejected, Provider, ProviderConfig, Transcriber, Transcript},
+    publishers::{Destination, Post, Published, TypefullyPublisher},
+    schedule::{describe_offset, parse_utc_offset, DefaultSchedule, Schedule},
+    styles::{normalize_name, StyleProfile, DEFAULT_STYLE, FORMAT_ONLY_STYLE},
+    thread,
+    typefully::{TypefullyClient, TypefullyError},
+    urls::UrlPolicy,
     DOWNLOAD_DIR,
 };
 
 type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
 
+/// Telegram rejects messages longer than this.
+const MAX_MESSAGE_CHARS: usize = 4096;
+
 pub async fn start(
     bot: Bot,
     dialog: BotDialogue,
@@ -56,8 +87,6 @@ pub async fn start(
     Ok(())
 }
 
-const TYPEFULLY_API_URL: &str = "https://api.typefully.com/v1/";
-
 pub async fn receive_typefully_api_key(
     bot: Bot,
     dialog: BotDialogue,
@@ -65,7 +94,7 @@ pub async fn receive_typefully_api_key(
     msg: Message,
 ) -> HandlerResult {
     // Get the api key from the message and try to call the typefully api to check if it's valid
-    let api_key = msg.text().unwrap_or_default();
+    let api_key = msg.text().unwrap_or_default().trim();
     let chat = msg.chat.clone();
     let Some(user) = db.get_user(chat.id.0 as u64).await? else {
         bot.send_message(
@@ -77,33 +106,49 @@ pub async fn receive_typefully_api_key(
         return Err(anyhow::anyhow!("User not found").into());
     };
 
-    let client = reqwest::Client::new();
-    let response = client
-        .get(format!("{}{}", TYPEFULLY_API_URL, "notifications/"))
-        .header("X-API-KEY", format!("Bearer {}", api_key))
-        .send()
-        .await?;
+    let verification = TypefullyPublisher::new(api_key.to_string()).verify().await;
 
-    if response.status().is_success() {
-        dialog.update(State::Start).await?;
+    delete_secret_message(&bot, &msg).await;
 
-        user.update_key(&db, api_key).await?;
+    match verification {
+        Ok(()) => {
+            dialog.update(State::Start).await?;
 
-        bot.send_message(msg.chat.id, "Alright, that looks good. Now you can start using the bot. Type /help to see the usage.").await?;
-    } else {
-        bot.send_message(
-            msg.chat.id,
-            "API key is invalid. Please provide a valid API key.",
-        )
-        .await?;
+            user.update_key(&db, api_key).await?;
+
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "Alright, your Typefully API key {} looks good. Now you can start using the bot. Type /help to see the usage.",
+                    mask_secret(api_key)
+                ),
+            )
+            .await?;
+        }
+        Err(TypefullyError::Unauthorized) => {
+            bot.send_message(
+                msg.chat.id,
+                "API key is invalid. Please provide a valid API key.",
+            )
+            .await?;
+        }
+        Err(e) => {
+            error!("Error verifying Typefully API key: {:?}", e);
+            bot.send_message(
+                msg.chat.id,
+                format!("{} Please send your API key again.", e),
+            )
+            .await?;
+        }
     }
+
     Ok(())
 }
 
 pub async fn help(bot: Bot, msg: Message) -> HandlerResult {
     let help_text = format!(
-        "{}\n\nHow to use:\n1. Use /start to set up your Typefully API key\n2. Send a voice note to the bot\n3. The bot will transcribe it and create a draft in Typefully\n\nNote: You have 5 minutes of free transcription. After that, you'll need to set your own OpenAI API key using /setapikey.",
-        BotCommand::descriptions().to_string()
+        "{}\n\nHow to use:\n1. Use /start to set up your Typefully API key\n2. Send a voice note, audio file, video or text message to the bot\n3. The bot will transcribe it and show you the generated post\n4. Reply to the post with corrected text or a voice note with change requests to edit it\n5. Press Approve to create a draft in Typefully\n\nNote: You have 5 minutes of free transcription. Text messages count as about 1 second per 15 characters. After that, you'll need to set your own OpenAI API key using /setapikey.",
+        BotCommand::descriptions()
     );
 
     bot.send_message(msg.chat.id, help_text).await?;
@@ -136,7 +181,7 @@ pub async fn handle_delete_confirmation(
 
         db.mark_user_deleted(user.telegram_id, total_usage).await?;
 
-        dialog.update(State::Start).await?;
+        dialog.exit().await?;
 
         bot.send_message(
             msg.chat.id,
@@ -179,223 +224,2517 @@ pub async fn inline_query_handler(bot: Bot, q: InlineQuery) -> HandlerResult {
     Ok(())
 }
 
-pub async fn handle_voice_note(bot: Bot, db: Arc<Database>, msg: Message) -> HandlerResult {
+pub async fn handle_voice_note(
+    bot: Bot,
+    db: Arc<Database>,
+    providers: Arc<ProviderConfig>,
+    msg: Message,
+    media: Media,
+) -> HandlerResult {
     let user = user_extractor(&bot, &db, &msg).await?;
-    let voice_note = msg.voice().unwrap();
-    let duration_seconds = voice_note.duration.seconds() as i32;
 
-    // Determine which API key to use
     let has_own_api_key = user.openai_api_key.is_some();
-    let api_key = match user.openai_api_key {
-        Some(user_api_key) => Ok(user_api_key),
-        None => {
-            if db.has_free_usage(user.telegram_id).await? {
-                std::env::var("OPENAI_API_KEY").map_err(|e| {
-                    error!("Failed to get OpenAI API key from env: {}", e);
-                    anyhow::anyhow!(e)
-                })
-            } else {
-                bot.send_message(
-                    msg.chat.id,
-                    "You have exceeded your free usage limit of 5 minutes. Please set your own OpenAI API key using /setapikey to continue using the voice transcription feature.",
-                )
-                .await?;
-                Err(anyhow::anyhow!("User exceeded free usage limit"))
-            }
-        }
-    }?;
+    let provider = resolve_provider(&bot, &db, &providers, &user, msg.chat.id).await?;
 
-    let download_path = PathBuf::new().join(DOWNLOAD_DIR);
-    let file = bot.get_file(&voice_note.file.id).await?;
-    let file_path = download_path.join(format!("{}.ogg", &file.unique_id));
-    let mut download_file = tokio::fs::File::create(&file_path).await?;
-
-    bot.download_file(&file.path, &mut download_file).await?;
-
-    bot.send_message(msg.chat.id, "Processing voice note..")
-        .await?;
-
-    let file_path = PathBuf::from(file_path);
-
-    // Use a clone for the transcription since we need the original path for cleanup
-    let transcription_path = file_path.clone();
-    let result = transcribe_voice_note(transcription_path, api_key.clone()).await;
+    let progress = Progress::start(&bot, msg.chat.id, Stage::Downloading).await?;
 
-    // Always try to clean up the file, regardless of transcription result
-    if let Err(e) = tokio::fs::remove_file(&file_path).await {
-        error!("Failed to clean up voice note file: {}", e);
-    }
+    let result = download_and_transcribe(
+        &bot,
+        &progress,
+        &media,
+        provider.transcriber.as_ref(),
+        user.transcription_language.as_deref(),
+    )
+    .await;
 
     match result {
         Ok(transcript) => {
             // Only track usage if using free credits
             if !has_own_api_key {
-                db.add_usage(user.telegram_id, duration_seconds).await?;
-            }
-
-            bot.send_message(msg.chat.id, "Transcription done.").await?;
-
-            match make_summary(
-                user.username.clone(),
-                transcript,
-                api_key,
-                user.rewrite_enabled,
-            )
-            .await
-            {
-                Ok(summary) => {
-                    bot.send_message(
-                        msg.chat.id,
-                        format!("This is what we got for you: \n\n{}\n\n", summary),
-                    )
-                    .await?;
-
-                    let api_key = user.typefully_api_key.unwrap_or_else(|| {
-                        panic!("User has no TypeFully API key. Please provide a valid API key.");
-                    });
-
-                    let client = reqwest::Client::new();
-                    let response = client
-                        .post(format!("{}{}", TYPEFULLY_API_URL, "drafts/"))
-                        .header("X-API-KEY", format!("Bearer {}", api_key))
-                        .json(&json!({
-                            "content": summary
-                        }))
-                        .send()
+                if let Err(e) = db.add_usage(user.telegram_id, media.duration_seconds).await {
+                    error!("Error tracking usage of user {}: {:?}", user.telegram_id, e);
+                    progress
+                        .fail("Something went wrong. Please try again.")
                         .await?;
+                    return Ok(());
+                }
+            }
 
-                    if !response.status().is_success() {
-                        bot.send_message(msg.chat.id, "Failed to create draft in TypeFully. Please check your API key and try again.")
+            let caption = msg.caption().unwrap_or_default();
+            if user.inbox_enabled || ideas::is_idea(caption) {
+                match save_idea(&db, &user, transcript, caption).await {
+                    Ok(saved) => progress.finish(saved, None).await?,
+                    Err(e) => {
+                        error!("Error saving idea by user {}: {:?}", user.telegram_id, e);
+                        progress
+                            .fail("An error occurred while saving the idea.")
                             .await?;
                     }
                 }
-                Err(e) => {
-                    error!("Error making summary by user {}: {:?}", user.telegram_id, e);
-                    bot.send_message(
-                        msg.chat.id,
-                        "An error occurred while transforming the post.",
-                    )
+            } else {
+                draft_from_transcript(&bot, &db, &provider, &user, progress, transcript, None)
                     .await?;
-                }
             }
         }
         Err(e) => {
             error!(
-                "Error transcribing voice note by user {}: {:?}",
-                user.telegram_id, e
+                "Error transcribing {} by user {}: {:?}",
+                media.kind.label(),
+                user.telegram_id,
+                e
             );
-            bot.send_message(
-                msg.chat.id,
-                "An error occurred while transcribing the voice note.",
-            )
-            .await?;
+            progress
+                .fail(&error_message(
+                    &e,
+                    &format!(
+                        "An error occurred while transcribing the {}.",
+                        media.kind.label()
+                    ),
+                ))
+                .await?;
         }
     }
 
     Ok(())
 }
 
-async fn user_extractor(bot: &Bot, db: &Arc<Database>, msg: &Message) -> anyhow::Result<User> {
-    let Some(user) = db.get_user(msg.chat.id.0 as u64).await? else {
-        bot.send_message(
-            msg.chat.id,
-            "Something went wrong. Please try again with /start.",
-        )
-        .await?;
+pub async fn handle_text_note(
+    bot: Bot,
+    db: Arc<Database>,
+    providers: Arc<ProviderConfig>,
+    msg: Message,
+) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+    let text = msg.text().unwrap_or_default().to_string();
 
-        return Err(anyhow::anyhow!("User not found").into());
+    let has_own_api_key = user.openai_api_key.is_some();
+
+    // Ideas are stored as they are and only need a provider, and count as usage, once they
+    // are drafted
+    if user.inbox_enabled || ideas::is_idea(&text) {
+        let transcript = Transcript {
+            text: ideas::strip_idea_tag(&text),
+            language: None,
+        };
+        let saved = save_idea(&db, &user, transcript, &text).await?;
+        bot.send_message(msg.chat.id, saved).await?;
+
+        return Ok(());
+    }
+
+    let provider = resolve_provider(&bot, &db, &providers, &user, msg.chat.id).await?;
+
+    // Only track usage if using free credits
+    if !has_own_api_key {
+        db.add_text_usage(user.telegram_id, text.chars().count())
+            .await?;
+    }
+
+    let transcript = Transcript {
+        text,
+        language: None,
     };
 
-    Ok(user)
+    let progress = Progress::start(&bot, msg.chat.id, Stage::Writing).await?;
+    draft_from_transcript(&bot, &db, &provider, &user, progress, transcript, None).await?;
+
+    Ok(())
 }
 
-pub async fn set_api_key(bot: Bot, dialog: BotDialogue, msg: Message) -> HandlerResult {
-    dialog.update(State::WaitingForOpenAiApiKey).await?;
+pub async fn start_collecting(
+    bot: Bot,
+    dialog: BotDialogue,
+    db: Arc<Database>,
+    msg: Message,
+) -> HandlerResult {
+    user_extractor(&bot, &db, &msg).await?;
+
+    if let Some(State::Collecting { notes, .. }) = dialog.get().await? {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "Already collecting ({} note(s) so far). Send more notes or /done to write the post.",
+                notes.len()
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    dialog
+        .update(State::Collecting {
+            notes: Vec::new(),
+            language: None,
+        })
+        .await?;
 
     bot.send_message(
         msg.chat.id,
-        "Please provide your OpenAI API key. You can get it from https://platform.openai.com/api-keys",
+        "Send me your notes one after another. Use /done when you're finished and I'll write one post from all of them.",
     )
     .await?;
 
     Ok(())
 }
 
-pub async fn receive_openai_api_key(
+pub async fn collect_media(
     bot: Bot,
     dialog: BotDialogue,
     db: Arc<Database>,
+    providers: Arc<ProviderConfig>,
     msg: Message,
+    media: Media,
+    (mut notes, language): (Vec<String>, Option<String>),
 ) -> HandlerResult {
-    let api_key = msg.text().unwrap_or_default();
     let user = user_extractor(&bot, &db, &msg).await?;
+    let provider = resolve_provider(&bot, &db, &providers, &user, msg.chat.id).await?;
 
-    // Update the user's OpenAI API key
-    user.update_openai_api_key(&db, api_key).await?;
-
-    dialog.update(State::Start).await?;
+    let progress = Progress::start(&bot, msg.chat.id, Stage::Downloading).await?;
 
-    bot.send_message(
-        msg.chat.id,
-        "Your OpenAI API key has been saved. You can now use the voice transcription feature.",
+    let transcript = match download_and_transcribe(
+        &bot,
+        &progress,
+        &media,
+        provider.transcriber.as_ref(),
+        user.transcription_language.as_deref(),
     )
-    .await?;
+    .await
+    {
+        Ok(transcript) => transcript,
+        Err(e) => {
+            error!(
+                "Error transcribing {} by user {}: {:?}",
+                media.kind.label(),
+                user.telegram_id,
+                e
+            );
+            progress
+                .fail(&error_message(
+                    &e,
+                    &format!(
+                        "An error occurred while transcribing the {}. Your other notes are kept, please send it again.",
+                        media.kind.label()
+                    ),
+                ))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    // Only track usage if using free credits
+    if user.openai_api_key.is_none() {
+        db.add_usage(user.telegram_id, media.duration_seconds)
+            .await?;
+    }
+
+    notes.push(transcript.text);
+    let language = language.or(transcript.language);
+    let added = collect(&dialog, notes, language).await?;
+    progress.finish(added, None).await?;
 
     Ok(())
 }
 
-pub async fn usage(bot: Bot, db: Arc<Database>, msg: Message) -> HandlerResult {
+pub async fn collect_text(
+    bot: Bot,
+    dialog: BotDialogue,
+    db: Arc<Database>,
+    msg: Message,
+    (mut notes, language): (Vec<String>, Option<String>),
+) -> HandlerResult {
     let user = user_extractor(&bot, &db, &msg).await?;
-    let total_usage = db.get_total_usage_seconds(user.telegram_id).await?;
-    let remaining_seconds = FREE_USAGE_LIMIT_SECONDS - total_usage;
+    let text = msg.text().unwrap_or_default().trim().to_string();
 
-    let message = if user.openai_api_key.is_some() {
-        "You are using your own OpenAI API key, so you have unlimited usage.".to_string()
-    } else if remaining_seconds <= 0 {
-        "You have used up all your free minutes. Please use /setapikey to set your own OpenAI API key to continue using the bot.".to_string()
-    } else {
-        let minutes = remaining_seconds / 60;
-        let seconds = remaining_seconds % 60;
-        format!(
-            "You have {} minutes and {} seconds of free transcription remaining.",
-            minutes, seconds
+    if text.is_empty() {
+        return Ok(());
+    }
+
+    // Only track usage if using free credits
+    if user.openai_api_key.is_none() {
+        db.add_text_usage(user.telegram_id, text.chars().count())
+            .await?;
+    }
+
+    notes.push(text);
+    let added = collect(&dialog, notes, language).await?;
+    bot.send_message(msg.chat.id, added).await?;
+
+    Ok(())
+}
+
+/// Stores the notes collected so far and returns the confirmation for the user.
+async fn collect(
+    dialog: &BotDialogue,
+    notes: Vec<String>,
+    language: Option<String>,
+) -> anyhow::Result<String> {
+    let count = notes.len();
+    dialog.update(State::Collecting { notes, language }).await?;
+
+    Ok(format!(
+        "Added note {}. Send the next one or /done to write the post.",
+        count
+    ))
+}
+
+pub async fn finish_collecting(
+    bot: Bot,
+    dialog: BotDialogue,
+    db: Arc<Database>,
+    providers: Arc<ProviderConfig>,
+    msg: Message,
+) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+
+    let Some(State::Collecting { notes, language }) = dialog.get().await? else {
+        bot.send_message(
+            msg.chat.id,
+            "There's nothing to combine. Use /collect first and send me your notes.",
         )
+        .await?;
+        return Ok(());
     };
 
-    bot.send_message(msg.chat.id, message).await?;
+    if notes.is_empty() {
+        dialog.update(State::Start).await?;
+        bot.send_message(
+            msg.chat.id,
+            "Stopped collecting, you didn't send any notes.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    // Keep the notes until a provider is available, so /done can be retried
+    let provider = resolve_provider(&bot, &db, &providers, &user, msg.chat.id).await?;
+    dialog.update(State::Start).await?;
+
+    let progress = Progress::start(&bot, msg.chat.id, Stage::Writing).await?;
+
+    let transcript = Transcript {
+        text: notes.join("\n\n"),
+        language,
+    };
+
+    draft_from_transcript(&bot, &db, &provider, &user, progress, transcript, None).await?;
+
     Ok(())
 }
 
-pub async fn set_typefully_key(bot: Bot, dialog: BotDialogue, msg: Message) -> HandlerResult {
-    dialog.update(State::WaitingForTypefullyApiKey).await?;
+/// Stores a transcript as an idea, tagged with the hashtags from `caption`, and returns the
+/// confirmation for the user.
+async fn save_idea(
+    db: &Database,
+    user: &User,
+    transcript: Transcript,
+    caption: &str,
+) -> anyhow::Result<String> {
+    let idea = db
+        .create_idea(
+            user.telegram_id,
+            &transcript.text,
+            transcript.language.as_deref(),
+            &ideas::tags(caption),
+        )
+        .await?;
 
-    bot.send_message(
-        msg.chat.id,
-        "Please provide your new Typefully API key. You can get it from https://typefully.com, go to settings -> API & Integrations.",
-    )
-    .await?;
+    let count = db.count_ideas(user.telegram_id).await?;
 
-    Ok(())
+    let mut message = format!("💡 Saved as idea ({} in your inbox).", count);
+    if !idea.tags.is_empty() {
+        message.push_str(&format!("\nTags: {}", idea.tags));
+    }
+    message.push_str("\nUse /ideas to turn it into a post.");
+
+    Ok(message)
 }
 
-pub async fn toggle_rewrite(bot: Bot, db: Arc<Database>, msg: Message) -> HandlerResult {
-    let user = user_extractor(&bot, &db, &msg).await?;
-    let new_value = user.toggle_rewrite(&db).await?;
+/// Turns a transcript into a pending draft and shows it for review in place of the progress
+/// message. The transcript is archived first unless it already is, as `transcript_id`.
+/// Returns whether a post could be written.
+async fn draft_from_transcript(
+    bot: &Bot,
+    db: &Database,
+    provider: &Provider,
+    user: &User,
+    progress: Progress,
+    transcript: Transcript,
+    transcript_id: Option<i64>,
+) -> anyhow::Result<bool> {
+    let drafts = match write_drafts(db, provider, user, &progress, &transcript, transcript_id).await
+    {
+        Ok(drafts) => drafts,
+        Err(e) => {
+            error!("Error making summary by user {}: {:?}", user.telegram_id, e);
+            progress
+                .fail(&error_message(
+                    &e,
+                    "An error occurred while transforming the post.",
+                ))
+                .await?;
+            return Ok(false);
+        }
+    };
 
-    let status = if new_value { "enabled" } else { "disabled" };
+    if let [draft] = drafts.as_slice() {
+        let message_id = progress.message_id();
+        progress
+            .finish(draft_preview(draft, user), Some(review_keyboard(draft.id)))
+            .await?;
 
-    bot.send_message(
-        msg.chat.id,
-        format!(
-            "AI rewriting is now {}. When {}, the bot will {}.",
-            status,
-            status,
-            if new_value {
-                "enhance and rewrite your voice notes for better social media impact"
-            } else {
-                "only format your voice notes without changing the content"
-            }
-        ),
+        db.set_pending_draft_message(draft.id, message_id.0).await?;
+
+        return Ok(true);
+    }
+
+    let chat_id = progress.chat_id();
+    let written = format!(
+        "✍️ Wrote {} variants in {}. Pick the one you like:",
+        drafts.len(),
+        progress.elapsed()
+    );
+    progress.finish(written, None).await?;
+
+    for (index, draft) in drafts.iter().enumerate() {
+        let preview = bot
+            .send_message(chat_id, variant_preview(draft, index + 1, drafts.len()))
+            .reply_markup(variant_keyboard(draft.id, index + 1))
+            .await?;
+
+        db.set_pending_draft_message(draft.id, preview.id.0).await?;
+    }
+
+    Ok(true)
+}
+
+/// Archives the transcript if needed, writes the posts and stores them as pending drafts.
+async fn write_drafts(
+    db: &Database,
+    provider: &Provider,
+    user: &User,
+    progress: &Progress,
+    transcript: &Transcript,
+    transcript_id: Option<i64>,
+) -> anyhow::Result<Vec<PendingDraft>> {
+    let transcript_id = match transcript_id {
+        Some(transcript_id) => transcript_id,
+        None => {
+            db.archive_transcript(user.telegram_id, &transcript.text)
+                .await?
+        }
+    };
+
+    if user.variant_count > 1 {
+        progress
+            .update_with(Stage::Writing, format!("{} variants", user.variant_count))
+            .await;
+    } else {
+        progress.update(Stage::Writing).await;
+    }
+
+    let posts = generate_post(db, provider, user, &transcript.text, user.variant_count).await?;
+
+    if let [summary] = posts.as_slice() {
+        let draft = db
+            .create_pending_draft(
+                user.telegram_id,
+                transcript_id,
+                &transcript.text,
+                transcript.language.as_deref(),
+                summary,
+            )
+            .await?;
+
+        return Ok(vec![draft]);
+    }
+
+    db.create_draft_variants(
+        user.telegram_id,
+        transcript_id,
+        &transcript.text,
+        transcript.language.as_deref(),
+        &posts,
+    )
+    .await
+}
+
+pub async fn handle_draft_action(
+    bot: Bot,
+    db: Arc<Database>,
+    providers: Arc<ProviderConfig>,
+    q: CallbackQuery,
+    action: DraftAction,
+) -> HandlerResult {
+    bot.answer_callback_query(&q.id).await?;
+
+    let Some(message) = q.regular_message() else {
+        return Ok(());
+    };
+    let chat_id = message.chat.id;
+
+    let draft_id = match action {
+        DraftAction::Approve(id)
+        | DraftAction::Regenerate(id)
+        | DraftAction::Schedule(id)
+        | DraftAction::Discard(id)
+        | DraftAction::Pick(id) => id,
+    };
+
+    let Some(user) = db.get_user(q.from.id.0).await? else {
+        bot.send_message(
+            chat_id,
+            "Something went wrong. Please try again with /start.",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    let Some(draft) = db.get_pending_draft(draft_id, user.telegram_id).await? else {
+        bot.edit_message_reply_markup(chat_id, message.id).await?;
+        bot.send_message(chat_id, "This draft is no longer available.")
+            .await?;
+        return Ok(());
+    };
+
+    match action {
+        DraftAction::Approve(_) => {
+            let publisher = match user.destination().publisher(&user) {
+                Ok(publisher) => publisher,
+                Err(e) => {
+                    bot.send_message(chat_id, e.to_string()).await?;
+                    return Ok(());
+                }
+            };
+
+            let post = Post {
+                telegram_id: user.telegram_id,
+                content: &draft.content,
+                schedule: draft.schedule(&user),
+                share: user.share_enabled,
+            };
+
+            let published = match publisher.publish(&post).await {
+                Ok(published) => published,
+                Err(e) => {
+                    error!(
+                        "Error publishing draft by user {} to {}: {:?}",
+                        user.telegram_id,
+                        publisher.name(),
+                        e
+                    );
+                    bot.send_message(chat_id, e.to_string()).await?;
+                    return Ok(());
+                }
+            };
+
+            db.delete_pending_draft(draft.id).await?;
+            if let Some(transcript_id) = draft.transcript_id {
+                db.update_archived_post(transcript_id, &draft.content, &published)
+                    .await?;
+            }
+
+            let sent = bot.edit_message_text(
+                chat_id,
+                message.id,
+                draft_preview_with(
+                    &draft,
+                    &user,
+                    &format!("\n✅ Sent to {}.", publisher.name()),
+                ),
+            );
+            match published_keyboard(&published) {
+                Some(keyboard) => sent.reply_markup(keyboard).await?,
+                None => sent.await?,
+            };
+        }
+        DraftAction::Regenerate(_) => {
+            let provider = resolve_provider(&bot, &db, &providers, &user, chat_id).await?;
+
+            // Only track usage if using free credits
+            if user.openai_api_key.is_none() {
+                db.add_text_usage(user.telegram_id, draft.transcript.chars().count())
+                    .await?;
+            }
+
+            match generate_post(&db, &provider, &user, &draft.transcript, 1).await {
+                Ok(mut posts) => {
+                    let summary = posts.remove(0);
+                    db.update_pending_draft_content(draft.id, &summary).await?;
+
+                    let draft = PendingDraft {
+                        content: summary,
+                        ..draft
+                    };
+
+                    bot.edit_message_text(chat_id, message.id, draft_preview(&draft, &user))
+                        .reply_markup(review_keyboard(draft.id))
+                        .await?;
+                }
+                Err(e) => {
+                    error!("Error making summary by user {}: {:?}", user.telegram_id, e);
+                    bot.send_message(
+                        chat_id,
+                        error_message(&e, "An error occurred while transforming the post."),
+                    )
+                    .await?;
+                }
+            }
+        }
+        DraftAction::Schedule(_) => {
+            db.set_pending_draft_schedule(draft.id, Schedule::NextFreeSlot)
+                .await?;
+
+            let draft = PendingDraft {
+                schedule: Some(Schedule::NextFreeSlot.to_db_value()),
+                ..draft
+            };
+
+            bot.edit_message_text(chat_id, message.id, draft_preview(&draft, &user))
+                .reply_markup(review_keyboard(draft.id))
+                .await?;
+        }
+        DraftAction::Pick(_) => {
+            discard_other_variants(&bot, &db, chat_id, &draft).await?;
+
+            bot.edit_message_text(chat_id, message.id, draft_preview(&draft, &user))
+                .reply_markup(review_keyboard(draft.id))
+                .await?;
+        }
+        DraftAction::Discard(_) => {
+            db.delete_pending_draft(draft.id).await?;
+
+            bot.edit_message_text(chat_id, message.id, "🗑 Draft discarded.")
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Keeps `draft` if it's one of several variants and removes the others from the chat.
+async fn discard_other_variants(
+    bot: &Bot,
+    db: &Database,
+    chat_id: ChatId,
+    draft: &PendingDraft,
+) -> anyhow::Result<()> {
+    for message_id in db.pick_draft_variant(draft).await? {
+        if let Err(e) = bot
+            .delete_message(chat_id, MessageId(message_id as i32))
+            .await
+        {
+            error!("Error deleting variant message {}: {:?}", message_id, e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Finds the pending draft whose preview the message is replying to.
+pub async fn replied_draft(msg: Message, db: Arc<Database>) -> Option<PendingDraft> {
+    let reply = msg.reply_to_message()?;
+
+    db.get_pending_draft_by_message(msg.chat.id.0, reply.id.0)
+        .await
+        .ok()
+        .flatten()
+}
+
+pub async fn handle_draft_reply(
+    bot: Bot,
+    db: Arc<Database>,
+    providers: Arc<ProviderConfig>,
+    msg: Message,
+    draft: PendingDraft,
+) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+
+    let (content, progress) = if let Some(text) = msg.text() {
+        (text.to_string(), None)
+    } else if let Some(media) = Media::from_message(msg.clone()) {
+        let has_own_api_key = user.openai_api_key.is_some();
+        let provider = resolve_provider(&bot, &db, &providers, &user, msg.chat.id).await?;
+
+        let progress = Progress::start(&bot, msg.chat.id, Stage::Downloading).await?;
+
+        let change_request = match download_and_transcribe(
+            &bot,
+            &progress,
+            &media,
+            provider.transcriber.as_ref(),
+            user.transcription_language.as_deref(),
+        )
+        .await
+        {
+            Ok(change_request) => change_request.text,
+            Err(e) => {
+                error!(
+                    "Error transcribing voice note by user {}: {:?}",
+                    user.telegram_id, e
+                );
+                progress
+                    .fail(&error_message(
+                        &e,
+                        "An error occurred while transcribing the voice note.",
+                    ))
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        if !has_own_api_key {
+            db.add_usage(user.telegram_id, media.duration_seconds)
+                .await?;
+        }
+
+        progress.update(Stage::Revising).await;
+
+        match revise_summary(
+            provider.writer.as_ref(),
+            &user.username,
+            &draft.content,
+            &change_request,
+        )
+        .await
+        {
+            Ok(revised) => (revised, Some(progress)),
+            Err(e) => {
+                error!("Error revising post by user {}: {:?}", user.telegram_id, e);
+                progress
+                    .fail(&error_message(
+                        &e,
+                        "An error occurred while revising the post.",
+                    ))
+                    .await?;
+                return Ok(());
+            }
+        }
+    } else {
+        bot.send_message(
+            msg.chat.id,
+            "Reply with the corrected text or a voice note describing the changes.",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    db.update_pending_draft_content(draft.id, &content).await?;
+    discard_other_variants(&bot, &db, msg.chat.id, &draft).await?;
+
+    let draft = PendingDraft { content, ..draft };
+    let preview = msg.reply_to_message().unwrap();
+
+    bot.edit_message_text(msg.chat.id, preview.id, draft_preview(&draft, &user))
+        .reply_markup(review_keyboard(draft.id))
+        .await?;
+
+    match progress {
+        Some(progress) => {
+            let updated = format!("✅ Updated the draft above in {}.", progress.elapsed());
+            progress.finish(updated, None).await?;
+        }
+        None => {
+            bot.send_message(msg.chat.id, "Updated the draft above.")
+                .reply_parameters(ReplyParameters::new(preview.id))
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn download_and_transcribe(
+    bot: &Bot,
+    progress: &Progress,
+    media: &Media,
+    transcriber: &dyn Transcriber,
+    language: Option<&str>,
+) -> anyhow::Result<Transcript> {
+    media.check_downloadable()?;
+
+    let download_path = PathBuf::new().join(DOWNLOAD_DIR);
+    let file = bot.get_file(&media.file_id).await?;
+    let file_path = download_path.join(format!("{}.{}", &file.unique_id, media.kind.extension()));
+    let mut download_file = tokio::fs::File::create(&file_path).await?;
+
+    bot.download_file(&file.path, &mut download_file).await?;
+
+    let mut cleanup = vec![file_path.clone()];
+
+    let audio_path = if media.kind.needs_extraction() {
+        let audio_path = download_path.join(format!("{}-audio.mp3", &file.unique_id));
+        cleanup.push(audio_path.clone());
+
+        progress
+            .update_with(Stage::Downloading, "extracting audio")
+            .await;
+
+        extract_audio(&file_path, &audio_path)
+            .await
+            .map(|()| audio_path)
+    } else {
+        Ok(file_path)
+    };
+
+    let result = match audio_path {
+        Ok(audio_path) => {
+            transcribe_in_parts(
+                progress,
+                &audio_path,
+                media.duration_seconds,
+                transcriber,
+                language,
+                &mut cleanup,
+            )
+            .await
+        }
+        Err(e) => Err(e),
+    };
+
+    // Always try to clean up the files, regardless of transcription result
+    for path in cleanup {
+        if let Err(e) = tokio::fs::remove_file(&path).await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                error!("Failed to clean up media file: {}", e);
+            }
+        }
+    }
+
+    result
+}
+
+/// Transcribes recordings above the upload limit segment by segment, reporting progress.
+async fn transcribe_in_parts(
+    progress: &Progress,
+    audio_path: &Path,
+    duration_seconds: i32,
+    transcriber: &dyn Transcriber,
+    language: Option<&str>,
+    cleanup: &mut Vec<PathBuf>,
+) -> anyhow::Result<Transcript> {
+    let parts = split_for_upload(audio_path, duration_seconds).await?;
+
+    if parts.len() == 1 {
+        progress.update(Stage::Transcribing).await;
+        return transcriber.transcribe(audio_path, language).await;
+    }
+
+    cleanup.extend(parts.iter().cloned());
+
+    let total = parts.len();
+    let mut transcripts = Vec::with_capacity(total);
+    let mut detected_language = None;
+    for (index, part) in parts.into_iter().enumerate() {
+        progress
+            .update_with(Stage::Transcribing, format!("part {}/{}", index + 1, total))
+            .await;
+
+        let transcript = transcriber.transcribe(&part, language).await?;
+        detected_language = detected_language.or(transcript.language);
+        transcripts.push(transcript.text);
+    }
+
+    Ok(Transcript {
+        text: stitch_transcripts(transcripts),
+        language: detected_language,
+    })
+}
+
+/// Generates `variants` alternative posts for the transcript in the user's style.
+async fn generate_post(
+    db: &Database,
+    provider: &Provider,
+    user: &User,
+    transcript: &str,
+    variants: i64,
+) -> anyhow::Result<Vec<String>> {
+    let style = db.selected_style_profile(user).await?;
+    let examples = db
+        .sample_style_examples(user.telegram_id, FEW_SHOT_EXAMPLES)
+        .await?;
+    let options = WritingOptions {
+        style: &style,
+        examples: &examples,
+        output_language: user.output_language.as_deref(),
+    };
+
+    if user.thread_enabled {
+        let threads = make_thread(
+            provider.writer.as_ref(),
+            &user.username,
+            transcript,
+            &options,
+            thread::max_tweet_length(),
+            variants,
+        )
+        .await?;
+
+        Ok(threads.iter().map(|tweets| thread::join(tweets)).collect())
+    } else {
+        make_summary(
+            provider.writer.as_ref(),
+            &user.username,
+            transcript,
+            &options,
+            variants,
+        )
+        .await
+    }
+}
+
+fn variant_preview(draft: &PendingDraft, number: usize, total: usize) -> String {
+    let header = format!("Variant {}/{}:\n\n", number, total);
+
+    let mut footer = String::new();
+    let tweet_count = thread::tweet_count(&draft.content);
+    if tweet_count > 1 {
+        footer.push_str(&format!("\n\n🧵 Thread with {} tweets", tweet_count));
+    }
+
+    fit_preview(&header, &draft.content, &footer)
+}
+
+fn draft_preview(draft: &PendingDraft, user: &User) -> String {
+    draft_preview_with(draft, user, "")
+}
+
+/// Like [`draft_preview`], with `status` after the schedule.
+fn draft_preview_with(draft: &PendingDraft, user: &User, status: &str) -> String {
+    let mut footer = String::from("\n\n");
+
+    let tweet_count = thread::tweet_count(&draft.content);
+    if tweet_count > 1 {
+        footer.push_str(&format!("🧵 Thread with {} tweets\n", tweet_count));
+    }
+
+    let spoken = draft.language.as_deref().map(language_name);
+    let written = user.output_language.as_deref().map(language_name);
+    match (spoken, written) {
+        (Some(spoken), written) => footer.push_str(&format!(
+            "🗣 Spoken: {} · Post: {}\n",
+            spoken,
+            written.as_deref().unwrap_or(&spoken)
+        )),
+        (None, Some(written)) => footer.push_str(&format!("🗣 Post: {}\n", written)),
+        (None, None) => {}
+    }
+
+    footer.push_str(&format!(
+        "📅 Schedule: {}{}",
+        draft.schedule(user).describe(user.utc_offset()),
+        status
+    ));
+
+    fit_preview("This is what we got for you: \n\n", &draft.content, &footer)
+}
+
+/// The error to show the user, or `fallback` for errors they can't do anything about.
+fn error_message(e: &anyhow::Error, fallback: &str) -> String {
+    if let Some(rejected) = e.downcast_ref::<ModelRejected>() {
+        return rejected.to_string();
+    }
+    if let Some(too_large) = e.downcast_ref::<TooLargeToDownload>() {
+        return too_large.to_string();
+    }
+
+    fallback.to_string()
+}
+
+/// Picks the user's provider, using the operator's key while the user has free usage left.
+async fn resolve_provider(
+    bot: &Bot,
+    db: &Database,
+    providers: &ProviderConfig,
+    user: &User,
+    chat_id: ChatId,
+) -> anyhow::Result<Provider> {
+    let api_key = resolve_openai_api_key(bot, db, user, chat_id).await?;
+
+    if let Err(e) = providers.endpoint(user).check().await {
+        bot.send_message(
+            chat_id,
+            format!(
+                "Your custom server can't be used: {} Please choose another one with /provider.",
+                e
+            ),
+        )
+        .await?;
+        return Err(e.into());
+    }
+
+    Ok(providers.provider(user, api_key))
+}
+
+/// Picks the user's own OpenAI key, falling back to the operator key while free usage remains.
+async fn resolve_openai_api_key(
+    bot: &Bot,
+    db: &Database,
+    user: &User,
+    chat_id: ChatId,
+) -> anyhow::Result<String> {
+    match &user.openai_api_key {
+        Some(user_api_key) => Ok(user_api_key.clone()),
+        None => {
+            if db.has_free_usage(user.telegram_id).await? {
+                std::env::var("OPENAI_API_KEY").map_err(|e| {
+                    error!("Failed to get OpenAI API key from env: {}", e);
+                    anyhow::anyhow!(e)
+                })
+            } else {
+                bot.send_message(
+                    chat_id,
+                    "You have exceeded your free usage limit of 5 minutes. Please set your own OpenAI API key using /setapikey to continue using the voice transcription feature.",
+                )
+                .await?;
+                Err(anyhow::anyhow!("User exceeded free usage limit"))
+            }
+        }
+    }
+}
+
+async fn user_extractor(bot: &Bot, db: &Arc<Database>, msg: &Message) -> anyhow::Result<User> {
+    let Some(user) = db.get_user(msg.chat.id.0 as u64).await? else {
+        bot.send_message(
+            msg.chat.id,
+            "Something went wrong. Please try again with /start.",
+        )
+        .await?;
+
+        return Err(anyhow::anyhow!("User not found"));
+    };
+
+    Ok(user)
+}
+
+pub async fn set_api_key(bot: Bot, dialog: BotDialogue, msg: Message) -> HandlerResult {
+    dialog.update(State::WaitingForOpenAiApiKey).await?;
+
+    bot.send_message(
+        msg.chat.id,
+        "Please provide your OpenAI API key. You can get it from https://platform.openai.com/api-keys",
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub async fn receive_openai_api_key(
+    bot: Bot,
+    dialog: BotDialogue,
+    db: Arc<Database>,
+    providers: Arc<ProviderConfig>,
+    msg: Message,
+) -> HandlerResult {
+    let api_key = msg.text().unwrap_or_default().trim();
+    let user = user_extractor(&bot, &db, &msg).await?;
+
+    // Check the key against the server it will be used with, which may not be OpenAI
+    let endpoint = providers.key_endpoint(&user);
+    let validation = match endpoint.check().await {
+        Ok(()) => OpenAiApi::new(&endpoint).validate_key(api_key).await,
+        Err(e) => Err(KeyValidationError::Other(e.to_string())),
+    };
+
+    delete_secret_message(&bot, &msg).await;
+
+    match validation {
+        Ok(()) => {
+            // Update the user's OpenAI API key
+            user.update_openai_api_key(&db, api_key).await?;
+
+            dialog.update(State::Start).await?;
+
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "Your OpenAI API key {} has been saved. You can now use the voice transcription feature.",
+                    mask_secret(api_key)
+                ),
+            )
+            .await?;
+        }
+        Err(e) => {
+            if let KeyValidationError::Other(_) = e {
+                error!("Error validating OpenAI API key: {:?}", e);
+            }
+
+            bot.send_message(msg.chat.id, e.to_string()).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes a message containing an API key so it doesn't stay in the chat history.
+async fn delete_secret_message(bot: &Bot, msg: &Message) {
+    if let Err(e) = bot.delete_message(msg.chat.id, msg.id).await {
+        error!("Failed to delete message containing an API key: {}", e);
+    }
+}
+
+pub async fn keys(bot: Bot, db: Arc<Database>, msg: Message) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+
+    let typefully = match &user.typefully_api_key {
+        Some(key) => mask_secret(key),
+        None => "not set, use /settypefullykey".to_string(),
+    };
+    let openai = match &user.openai_api_key {
+        Some(key) => mask_secret(key),
+        None => "not set, using the free tier (/setapikey to add yours)".to_string(),
+    };
+
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "Your configured keys:\n\nTypefully: {}\nOpenAI: {}",
+            typefully, openai
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub async fn usage(bot: Bot, db: Arc<Database>, msg: Message) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+    let total_usage = db.get_total_usage_seconds(user.telegram_id).await?;
+    let remaining_seconds = FREE_USAGE_LIMIT_SECONDS - total_usage;
+
+    let message = if user.openai_api_key.is_some() {
+        "You are using your own OpenAI API key, so you have unlimited usage.".to_string()
+    } else if remaining_seconds <= 0 {
+        "You have used up all your free minutes. Please use /setapikey to set your own OpenAI API key to continue using the bot.".to_string()
+    } else {
+        let minutes = remaining_seconds / 60;
+        let seconds = remaining_seconds % 60;
+        format!(
+            "You have {} minutes and {} seconds of free transcription remaining.",
+            minutes, seconds
+        )
+    };
+
+    bot.send_message(msg.chat.id, message).await?;
+    Ok(())
+}
+
+pub async fn set_typefully_key(bot: Bot, dialog: BotDialogue, msg: Message) -> HandlerResult {
+    dialog.update(State::WaitingForTypefullyApiKey).await?;
+
+    bot.send_message(
+        msg.chat.id,
+        "Please provide your new Typefully API key. You can get it from https://typefully.com, go to settings -> API & Integrations.",
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub async fn toggle_rewrite(bot: Bot, db: Arc<Database>, msg: Message) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+
+    let rewrite = user.style_profile == FORMAT_ONLY_STYLE;
+    let style = if rewrite {
+        DEFAULT_STYLE
+    } else {
+        FORMAT_ONLY_STYLE
+    };
+    user.update_style_profile(&db, style).await?;
+
+    let status = if rewrite { "enabled" } else { "disabled" };
+
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "AI rewriting is now {}. When {}, the bot will {}. Use /styles to see all styles.",
+            status,
+            status,
+            if rewrite {
+                "enhance and rewrite your voice notes for better social media impact"
+            } else {
+                "only format your voice notes without changing the content"
+            }
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub async fn list_styles(bot: Bot, db: Arc<Database>, msg: Message) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+    let selected = db.selected_style_profile(&user).await?;
+    let custom = db.list_style_profiles(user.telegram_id).await?;
+
+    let line = |style: &StyleProfile| {
+        let marker = if style.name == selected.name {
+            "✅"
+        } else {
+            "•"
+        };
+        format!("{} {}\n", marker, style.name)
+    };
+
+    let mut text = String::from("Built-in styles:\n");
+    for style in StyleProfile::built_ins() {
+        text.push_str(&line(&style));
+    }
+
+    text.push_str("\nYour styles:\n");
+    if custom.is_empty() {
+        text.push_str("None yet.\n");
+    }
+    for style in &custom {
+        text.push_str(&line(style));
+    }
+
+    text.push_str(
+        "\nSelect a style with /style <name>, create one with /newstyle <name>, change one with /editstyle <name> or remove it with /deletestyle <name>.",
+    );
+
+    bot.send_message(msg.chat.id, text).await?;
+
+    Ok(())
+}
+
+pub async fn select_style(
+    bot: Bot,
+    db: Arc<Database>,
+    msg: Message,
+    name: String,
+) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+
+    if name.trim().is_empty() {
+        let selected = db.selected_style_profile(&user).await?;
+
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "Your posts are currently written in the '{}' style. Use /styles to see all styles.",
+                selected.name
+            ),
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    let name = match normalize_style_name(&bot, &msg, &name).await? {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+
+    let exists = StyleProfile::built_in(&name).is_some()
+        || db
+            .get_style_profile(user.telegram_id, &name)
+            .await?
+            .is_some();
+
+    if !exists {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "There is no style named '{}'. Use /styles to see all styles.",
+                name
+            ),
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    user.update_style_profile(&db, &name).await?;
+
+    bot.send_message(
+        msg.chat.id,
+        format!("New posts will be written in the '{}' style.", name),
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub async fn new_style(
+    bot: Bot,
+    dialog: BotDialogue,
+    db: Arc<Database>,
+    msg: Message,
+    name: String,
+) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+
+    let name = match normalize_style_name(&bot, &msg, &name).await? {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+
+    if StyleProfile::built_in(&name).is_some() {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "'{}' is a built-in style. Please choose another name.",
+                name
+            ),
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    if db
+        .get_style_profile(user.telegram_id, &name)
+        .await?
+        .is_some()
+    {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "You already have a style named '{}'. Use /editstyle {} to change it.",
+                name, name
+            ),
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "Please send the instructions for the '{}' style. Describe how your posts should sound, e.g. tone, length, structure and words to avoid.",
+            name
+        ),
+    )
+    .await?;
+
+    dialog
+        .update(State::WaitingForStyleInstructions { name })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn edit_style(
+    bot: Bot,
+    dialog: BotDialogue,
+    db: Arc<Database>,
+    msg: Message,
+    name: String,
+) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+
+    let name = match normalize_style_name(&bot, &msg, &name).await? {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+
+    if StyleProfile::built_in(&name).is_some() {
+        bot.send_message(
+            msg.chat.id,
+            "Built-in styles can't be changed. Create your own with /newstyle <name>.",
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    let Some(style) = db.get_style_profile(user.telegram_id, &name).await? else {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "There is no style named '{}'. Create it with /newstyle {}.",
+                name, name
+            ),
+        )
+        .await?;
+
+        return Ok(());
+    };
+
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "These are the current instructions for the '{}' style:\n\n{}\n\nPlease send the new instructions.",
+            style.name, style.instructions
+        ),
+    )
+    .await?;
+
+    dialog
+        .update(State::WaitingForStyleInstructions { name })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn receive_style_instructions(
+    bot: Bot,
+    dialog: BotDialogue,
+    db: Arc<Database>,
+    msg: Message,
+    name: String,
+) -> HandlerResult {
+    let instructions = msg.text().unwrap_or_default().trim();
+
+    if instructions.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            "Please send the instructions for your style as a text message.",
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    let user = user_extractor(&bot, &db, &msg).await?;
+    db.save_style_profile(user.telegram_id, &name, instructions)
+        .await?;
+
+    dialog.update(State::Start).await?;
+
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "Saved the '{}' style. Use /style {} to write your posts with it.",
+            name, name
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete_style(
+    bot: Bot,
+    db: Arc<Database>,
+    msg: Message,
+    name: String,
+) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+
+    let name = match normalize_style_name(&bot, &msg, &name).await? {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+
+    if StyleProfile::built_in(&name).is_some() {
+        bot.send_message(msg.chat.id, "Built-in styles can't be deleted.")
+            .await?;
+
+        return Ok(());
+    }
+
+    let message = if db.delete_style_profile(user.telegram_id, &name).await? {
+        format!("Deleted the '{}' style.", name)
+    } else {
+        format!("There is no style named '{}'.", name)
+    };
+
+    bot.send_message(msg.chat.id, message).await?;
+
+    Ok(())
+}
+
+/// Replies with the validation error and returns `None` if the name can't be used.
+async fn normalize_style_name(
+    bot: &Bot,
+    msg: &Message,
+    name: &str,
+) -> anyhow::Result<Option<String>> {
+    match normalize_name(name) {
+        Ok(name) => Ok(Some(name)),
+        Err(e) => {
+            bot.send_message(msg.chat.id, format!("{}.", e)).await?;
+            Ok(None)
+        }
+    }
+}
+
+pub async fn import_posts(bot: Bot, db: Arc<Database>, msg: Message) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+
+    let Some(api_key) = user.typefully_api_key.clone() else {
+        bot.send_message(
+            msg.chat.id,
+            "Please set your Typefully API key with /settypefullykey first.",
+        )
+        .await?;
+
+        return Ok(());
+    };
+
+    let posts = match TypefullyClient::new(api_key).recently_published().await {
+        Ok(posts) => posts,
+        Err(e) => {
+            error!(
+                "Error loading published posts of user {}: {:?}",
+                user.telegram_id, e
+            );
+            bot.send_message(
+                msg.chat.id,
+                format!("Couldn't load your published posts. {}", e),
+            )
+            .await?;
+
+            return Ok(());
+        }
+    };
+
+    let mut remaining = MAX_STYLE_EXAMPLES - db.count_style_examples(user.telegram_id).await?;
+    let mut imported = 0;
+
+    for post in posts {
+        if remaining <= 0 {
+            break;
+        }
+
+        let Some(content) = post.text.or(post.text_first_tweet) else {
+            continue;
+        };
+        let content = content.trim();
+
+        if !content.is_empty()
+            && db
+                .add_style_example(user.telegram_id, content, "typefully")
+                .await?
+        {
+            imported += 1;
+            remaining -= 1;
+        }
+    }
+
+    let mut message = if imported == 0 {
+        "No new published posts found.".to_string()
+    } else {
+        format!(
+            "Imported {} published posts. New posts will be written in their style.",
+            imported
+        )
+    };
+
+    if remaining <= 0 {
+        message.push_str(&format!(
+            " You've reached the limit of {} examples.",
+            MAX_STYLE_EXAMPLES
+        ));
+    }
+    message.push_str(" Use /examples to review them.");
+
+    bot.send_message(msg.chat.id, message).await?;
+
+    Ok(())
+}
+
+pub async fn add_example(
+    bot: Bot,
+    dialog: BotDialogue,
+    db: Arc<Database>,
+    msg: Message,
+    example: String,
+) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+
+    if example.trim().is_empty() {
+        dialog.update(State::WaitingForExample).await?;
+
+        bot.send_message(
+            msg.chat.id,
+            "Please send a post you wrote. It will be used as an example of your writing style.",
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    store_example(&bot, &db, &user, msg.chat.id, example.trim()).await?;
+
+    Ok(())
+}
+
+pub async fn receive_example(
+    bot: Bot,
+    dialog: BotDialogue,
+    db: Arc<Database>,
+    msg: Message,
+) -> HandlerResult {
+    let example = msg.text().unwrap_or_default().trim();
+
+    if example.is_empty() {
+        bot.send_message(msg.chat.id, "Please send your post as a text message.")
+            .await?;
+
+        return Ok(());
+    }
+
+    let user = user_extractor(&bot, &db, &msg).await?;
+    store_example(&bot, &db, &user, msg.chat.id, example).await?;
+
+    dialog.update(State::Start).await?;
+
+    Ok(())
+}
+
+async fn store_example(
+    bot: &Bot,
+    db: &Database,
+    user: &User,
+    chat_id: ChatId,
+    example: &str,
+) -> anyhow::Result<()> {
+    if db.count_style_examples(user.telegram_id).await? >= MAX_STYLE_EXAMPLES {
+        bot.send_message(
+            chat_id,
+            format!(
+                "You've reached the limit of {} examples. Delete some with /examples first.",
+                MAX_STYLE_EXAMPLES
+            ),
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    let message = if db
+        .add_style_example(user.telegram_id, example, "manual")
+        .await?
+    {
+        "Saved your example. New posts will be written in its style."
+    } else {
+        "You already saved this example."
+    };
+
+    bot.send_message(chat_id, message).await?;
+
+    Ok(())
+}
+
+pub async fn list_examples(bot: Bot, db: Arc<Database>, msg: Message) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+    let examples = db.list_style_examples(user.telegram_id).await?;
+
+    bot.send_message(msg.chat.id, examples_overview(&examples))
+        .reply_markup(examples_keyboard(&examples))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn handle_example_action(
+    bot: Bot,
+    db: Arc<Database>,
+    q: CallbackQuery,
+    action: ExampleAction,
+) -> HandlerResult {
+    bot.answer_callback_query(&q.id).await?;
+
+    let Some(message) = q.regular_message() else {
+        return Ok(());
+    };
+
+    let telegram_id = q.from.id.0 as i64;
+
+    match action {
+        ExampleAction::Delete(id) => {
+            // Pressing a button twice would leave the list unchanged, which Telegram rejects
+            if !db.delete_style_example(id, telegram_id).await? {
+                return Ok(());
+            }
+        }
+    }
+
+    let examples = db.list_style_examples(telegram_id).await?;
+
+    bot.edit_message_text(message.chat.id, message.id, examples_overview(&examples))
+        .reply_markup(examples_keyboard(&examples))
+        .await?;
+
+    Ok(())
+}
+
+fn examples_overview(examples: &[StyleExample]) -> String {
+    if examples.is_empty() {
+        return "You have no writing examples yet. Use /importposts to import your published Typefully posts or /addexample to add one.".to_string();
+    }
+
+    let mut overview = format!(
+        "Your writing examples ({}/{}):\n\n",
+        examples.len(),
+        MAX_STYLE_EXAMPLES
+    );
+
+    for (index, example) in examples.iter().enumerate() {
+        overview.push_str(&format!(
+            "{}. {} ({})\n",
+            index + 1,
+            excerpt(&example.content, 80),
+            example.source
+        ));
+    }
+
+    overview.push_str("\nPress a button to delete an example.");
+
+    overview
+}
+
+/// The text on a single line, cut after `max_chars` characters.
+fn excerpt(text: &str, max_chars: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if text.chars().count() <= max_chars {
+        return text;
+    }
+
+    let cut: String = text.chars().take(max_chars).collect();
+    format!("{}…", cut.trim_end())
+}
+
+pub async fn history(bot: Bot, db: Arc<Database>, msg: Message) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+    let (overview, keyboard) = history_page(&db, &user, 0).await?;
+
+    bot.send_message(msg.chat.id, overview)
+        .reply_markup(keyboard)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn search(bot: Bot, db: Arc<Database>, msg: Message, query: String) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+
+    if query.trim().is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            "Please tell me what to look for, e.g. /search pricing idea",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let transcripts = db
+        .search_archived_transcripts(user.telegram_id, &query)
+        .await?;
+
+    if transcripts.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            format!("No transcripts found for '{}'.", query.trim()),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let overview = transcripts_overview(
+        &format!("Best matches for '{}':", query.trim()),
+        &transcripts,
+        &user,
+    );
+
+    bot.send_message(msg.chat.id, overview)
+        .reply_markup(search_keyboard(&transcripts))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn handle_history_action(
+    bot: Bot,
+    db: Arc<Database>,
+    q: CallbackQuery,
+    action: HistoryAction,
+) -> HandlerResult {
+    bot.answer_callback_query(&q.id).await?;
+
+    let Some(message) = q.regular_message() else {
+        return Ok(());
+    };
+    let chat_id = message.chat.id;
+
+    let Some(user) = db.get_user(q.from.id.0).await? else {
+        bot.send_message(
+            chat_id,
+            "Something went wrong. Please try again with /start.",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    match action {
+        HistoryAction::Page(page) => {
+            let (overview, keyboard) = history_page(&db, &user, page.max(0)).await?;
+
+            bot.edit_message_text(chat_id, message.id, overview)
+                .reply_markup(keyboard)
+                .await?;
+        }
+        HistoryAction::Show(id) => {
+            let Some(transcript) = db.get_archived_transcript(id, user.telegram_id).await? else {
+                bot.send_message(chat_id, "This transcript is no longer available.")
+                    .await?;
+                return Ok(());
+            };
+
+            bot.send_message(
+                chat_id,
+                fit_message(&format!(
+                    "🎙 Transcript from {}:\n\n{}",
+                    describe_date(transcript.created_at, &user),
+                    transcript.transcript
+                )),
+            )
+            .await?;
+            let Some(post) = transcript.post else {
+                return Ok(());
+            };
+            let post = bot.send_message(chat_id, fit_message(&format!("✍️ Post:\n\n{}", post)));
+            let links = Published {
+                draft_id: None,
+                edit_url: transcript.typefully_url,
+                share_url: transcript.typefully_share_url,
+            };
+            match published_keyboard(&links) {
+                Some(keyboard) => post.reply_markup(keyboard).await?,
+                None => post.await?,
+            };
+        }
+    }
+
+    Ok(())
+}
+
+async fn history_page(
+    db: &Database,
+    user: &User,
+    page: i64,
+) -> anyhow::Result<(String, InlineKeyboardMarkup)> {
+    let total = db.count_archived_transcripts(user.telegram_id).await?;
+    let transcripts = db.list_archived_transcripts(user.telegram_id, page).await?;
+
+    if transcripts.is_empty() {
+        return Ok((
+            "Your history is empty. Send a voice note and its transcript will show up here."
+                .to_string(),
+            history_keyboard(&[], page, false),
+        ));
+    }
+
+    let pages = (total + HISTORY_PAGE_SIZE - 1) / HISTORY_PAGE_SIZE;
+    let overview = transcripts_overview(
+        &format!("Your transcripts (page {}/{}):", page + 1, pages),
+        &transcripts,
+        user,
+    );
+
+    Ok((
+        overview,
+        history_keyboard(&transcripts, page, page + 1 < pages),
+    ))
+}
+
+fn transcripts_overview(title: &str, transcripts: &[ArchivedTranscript], user: &User) -> String {
+    let mut overview = format!("{}\n\n", title);
+
+    for (index, transcript) in transcripts.iter().enumerate() {
+        overview.push_str(&format!(
+            "{}. {}\n🎙 {}\n✍️ {}\n\n",
+            index + 1,
+            describe_date(transcript.created_at, user),
+            excerpt(&transcript.transcript, 100),
+            transcript
+                .post
+                .as_deref()
+                .map(|post| excerpt(post, 60))
+                .unwrap_or("No post yet".to_string())
+        ));
+    }
+
+    overview.push_str("Press a button to read a transcript in full.");
+
+    overview
+}
+
+fn describe_date(date: OffsetDateTime, user: &User) -> String {
+    date.to_offset(user.utc_offset())
+        .format(format_description!("[year]-[month]-[day] [hour]:[minute]"))
+        .unwrap_or_default()
+}
+
+/// Cuts text that doesn't fit into a single Telegram message.
+fn fit_message(text: &str) -> String {
+    cut_text(text, MAX_MESSAGE_CHARS)
+}
+
+/// Cuts the post in a preview, so the lines around it still fit into a single Telegram message.
+fn fit_preview(header: &str, content: &str, footer: &str) -> String {
+    let room = MAX_MESSAGE_CHARS.saturating_sub(header.chars().count() + footer.chars().count());
+
+    format!("{}{}{}", header, cut_text(content, room), footer)
+}
+
+/// The text with everything after `max_chars` characters replaced by an ellipsis.
+fn cut_text(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+
+    let cut: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    format!("{}…", cut)
+}
+
+pub async fn list_ideas(bot: Bot, db: Arc<Database>, msg: Message) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+    let ideas = db.list_ideas(user.telegram_id).await?;
+
+    bot.send_message(msg.chat.id, ideas_overview(&ideas, &user))
+        .reply_markup(ideas_keyboard(&ideas))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn handle_idea_action(
+    bot: Bot,
+    db: Arc<Database>,
+    providers: Arc<ProviderConfig>,
+    q: CallbackQuery,
+    action: IdeaAction,
+) -> HandlerResult {
+    bot.answer_callback_query(&q.id).await?;
+
+    let Some(message) = q.regular_message() else {
+        return Ok(());
+    };
+    let chat_id = message.chat.id;
+
+    let Some(user) = db.get_user(q.from.id.0).await? else {
+        bot.send_message(
+            chat_id,
+            "Something went wrong. Please try again with /start.",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    match action {
+        IdeaAction::Draft(id) => {
+            let Some(idea) = db.get_idea(id, user.telegram_id).await? else {
+                bot.send_message(chat_id, "This idea is no longer available.")
+                    .await?;
+                return Ok(());
+            };
+
+            let provider = resolve_provider(&bot, &db, &providers, &user, chat_id).await?;
+
+            // Only track usage if using free credits
+            if user.openai_api_key.is_none() {
+                db.add_text_usage(user.telegram_id, idea.transcript.chars().count())
+                    .await?;
+            }
+
+            let progress = Progress::start(&bot, chat_id, Stage::Writing).await?;
+
+            let transcript = Transcript {
+                text: idea.transcript,
+                language: idea.language,
+            };
+
+            // The list only changes once the idea became a draft
+            if !draft_from_transcript(
+                &bot,
+                &db,
+                &provider,
+                &user,
+                progress,
+                transcript,
+                idea.transcript_id,
+            )
+            .await?
+            {
+                return Ok(());
+            }
+            db.delete_idea(idea.id).await?;
+        }
+    }
+
+    let ideas = db.list_ideas(user.telegram_id).await?;
+
+    bot.edit_message_text(chat_id, message.id, ideas_overview(&ideas, &user))
+        .reply_markup(ideas_keyboard(&ideas))
+        .await?;
+
+    Ok(())
+}
+
+fn ideas_overview(ideas: &[Idea], user: &User) -> String {
+    if ideas.is_empty() {
+        return format!(
+            "Your inbox is empty. Add {} to a voice note's caption or use /toggleinbox to save notes as ideas.",
+            ideas::IDEA_TAG
+        );
+    }
+
+    let mut overview = "Your latest ideas:\n\n".to_string();
+
+    for (index, idea) in ideas.iter().enumerate() {
+        overview.push_str(&format!(
+            "{}. {} {}\n{}\n\n",
+            index + 1,
+            describe_date(idea.created_at, user),
+            idea.tags,
+            excerpt(&idea.transcript, 120)
+        ));
+    }
+
+    overview.push_str("Press a button to turn an idea into a post.");
+
+    overview
+}
+
+pub async fn set_variants(
+    bot: Bot,
+    db: Arc<Database>,
+    msg: Message,
+    variants: String,
+) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+
+    if variants.trim().is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "The bot currently writes {} variant(s) per note. Use /variants <1-{}> to change it.",
+                user.variant_count, MAX_VARIANTS
+            ),
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    let variant_count = match variants.trim().parse::<i64>() {
+        Ok(count) if (1..=MAX_VARIANTS).contains(&count) => count,
+        _ => {
+            bot.send_message(
+                msg.chat.id,
+                format!("Please choose a number between 1 and {}.", MAX_VARIANTS),
+            )
+            .await?;
+
+            return Ok(());
+        }
+    };
+
+    user.update_variant_count(&db, variant_count).await?;
+
+    let message = if variant_count == 1 {
+        "The bot will write a single post per note.".to_string()
+    } else {
+        format!(
+            "The bot will write {} variants per note. Pick the one you like best to review it.",
+            variant_count
+        )
+    };
+
+    bot.send_message(msg.chat.id, message).await?;
+
+    Ok(())
+}
+
+pub async fn set_provider(
+    bot: Bot,
+    db: Arc<Database>,
+    msg: Message,
+    provider: String,
+) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+    let provider = provider.trim();
+
+    if provider.is_empty() {
+        let current = match &user.ai_base_url {
+            Some(base_url) => format!("the OpenAI-compatible server at {}", base_url),
+            None => "the bot's default provider".to_string(),
+        };
+
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "Your notes are handled by {}. Use /provider <base url> to use an OpenAI-compatible server or /provider default to switch back.",
+                current
+            ),
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    if provider.eq_ignore_ascii_case("default") {
+        user.update_ai_base_url(&db, None).await?;
+
+        bot.send_message(
+            msg.chat.id,
+            "Your notes are handled by the bot's default provider again.",
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    if let Err(e) = UrlPolicy::ai_base_urls().check(provider).await {
+        bot.send_message(msg.chat.id, e.to_string()).await?;
+        return Ok(());
+    }
+
+    if user.openai_api_key.is_none() {
+        bot.send_message(
+            msg.chat.id,
+            "A custom server can only be used with your own API key. Please set it with /setapikey first.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    user.update_ai_base_url(&db, Some(provider)).await?;
+
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "Your notes will be handled by {} using your own API key.",
+            provider
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub async fn set_model(
+    bot: Bot,
+    db: Arc<Database>,
+    providers: Arc<ProviderConfig>,
+    msg: Message,
+    model: String,
+) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+    let has_own_api_key = user.openai_api_key.is_some();
+
+    let mut args = model.split_whitespace();
+    let (Some(kind), Some(model)) = (args.next(), args.next()) else {
+        let mut text = format!(
+            "Chat model: {}\nTranscription model: {}\n\nUse /model chat <model> or /model transcription <model> to change them, or /model chat default to go back to the default.",
+            providers.chat_model(&user),
+            providers.transcription_model(&user)
+        );
+
+        if !has_own_api_key {
+            text.push_str(&format!(
+                "\n\nOn the free tier you can choose these chat models: {}\nand these transcription models: {}\nSet your own API key with /setapikey to use any model.",
+                providers.free_chat_models().join(", "),
+                providers.free_transcription_models().join(", ")
+            ));
+        }
+
+        bot.send_message(msg.chat.id, text).await?;
+
+        return Ok(());
+    };
+
+    let (free_models, default_model) = match kind.to_lowercase().as_str() {
+        "chat" => (providers.free_chat_models(), providers.default_chat_model()),
+        "transcription" => (
+            providers.free_transcription_models(),
+            providers.default_transcription_model(),
+        ),
+        _ => {
+            bot.send_message(
+                msg.chat.id,
+                "Please choose which model to change: /model chat <model> or /model transcription <model>.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let model = (!model.eq_ignore_ascii_case("default")).then_some(model);
+
+    if let Some(model) = model {
+        if !has_own_api_key && !free_models.iter().any(|free| free == model) {
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "'{}' isn't available on the free tier. You can choose from: {}. Set your own API key with /setapikey to use any model.",
+                    model,
+                    free_models.join(", ")
+                ),
+            )
+            .await?;
+            return Ok(());
+        }
+    }
+
+    if kind.eq_ignore_ascii_case("chat") {
+        user.update_chat_model(&db, model).await?;
+    } else {
+        user.update_transcription_model(&db, model).await?;
+    }
+
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "Your {} model is now {}.",
+            kind.to_lowercase(),
+            model.unwrap_or(default_model)
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub async fn set_language(
+    bot: Bot,
+    db: Arc<Database>,
+    msg: Message,
+    language: String,
+) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+
+    let mut args = language.split_whitespace();
+    let (Some(kind), Some(language)) = (args.next(), args.next()) else {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "Spoken language: {}\nPost language: {}\n\nUse /language spoken <language> to help the transcription (or /language spoken auto to detect it) and /language post <language> to translate your posts (or /language post same to keep the spoken language).",
+                user.transcription_language
+                    .as_deref()
+                    .map(language_name)
+                    .unwrap_or_else(|| "detected automatically".to_string()),
+                user.output_language
+                    .as_deref()
+                    .map(language_name)
+                    .unwrap_or_else(|| "same as spoken".to_string())
+            ),
+        )
+        .await?;
+
+        return Ok(());
+    };
+
+    let kind = kind.to_lowercase();
+    let reset = match kind.as_str() {
+        "spoken" => "auto",
+        "post" => "same",
+        _ => {
+            bot.send_message(
+                msg.chat.id,
+                "Please choose which language to change: /language spoken <language> or /language post <language>.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let language = if language.eq_ignore_ascii_case(reset) {
+        None
+    } else {
+        match parse_language(language) {
+            Ok(language) => Some(language),
+            Err(e) => {
+                bot.send_message(
+                    msg.chat.id,
+                    format!("{}. Use a code like de or a name like German.", e),
+                )
+                .await?;
+                return Ok(());
+            }
+        }
+    };
+
+    let message = if kind == "spoken" {
+        user.update_transcription_language(&db, language).await?;
+
+        match language {
+            Some(language) => format!(
+                "Your notes will be transcribed as {}.",
+                language_name(language)
+            ),
+            None => "The spoken language will be detected automatically.".to_string(),
+        }
+    } else {
+        user.update_output_language(&db, language).await?;
+
+        match language {
+            Some(language) => format!("Your posts will be written in {}.", language_name(language)),
+            None => "Your posts will be written in the language you speak.".to_string(),
+        }
+    };
+
+    bot.send_message(msg.chat.id, message).await?;
+
+    Ok(())
+}
+
+pub async fn toggle_thread(bot: Bot, db: Arc<Database>, msg: Message) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+    let new_value = user.toggle_thread(&db).await?;
+
+    let message = if new_value {
+        format!(
+            "Thread mode is now enabled. Posts will be split into tweets of at most {} characters.",
+            thread::max_tweet_length()
+        )
+    } else {
+        "Thread mode is now disabled. Posts will be created as a single tweet.".to_string()
+    };
+
+    bot.send_message(msg.chat.id, message).await?;
+
+    Ok(())
+}
+
+pub async fn toggle_share(bot: Bot, db: Arc<Database>, msg: Message) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+    let new_value = user.toggle_share(&db).await?;
+
+    let message = if new_value {
+        "Share links are now enabled. New Typefully drafts get a public link you can send around."
+    } else {
+        "Share links are now disabled. New Typefully drafts are only visible to you."
+    };
+
+    bot.send_message(msg.chat.id, message).await?;
+
+    Ok(())
+}
+
+pub async fn toggle_inbox(bot: Bot, db: Arc<Database>, msg: Message) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+    let new_value = user.toggle_inbox(&db).await?;
+
+    let message = if new_value {
+        "Inbox mode is now enabled. Your notes will be saved as ideas, use /ideas to turn them into posts."
+            .to_string()
+    } else {
+        format!(
+            "Inbox mode is now disabled. Your notes will be turned into posts right away, add {} to save a single note as an idea.",
+            ideas::IDEA_TAG
+        )
+    };
+
+    bot.send_message(msg.chat.id, message).await?;
+
+    Ok(())
+}
+
+pub async fn set_default_schedule(
+    bot: Bot,
+    db: Arc<Database>,
+    msg: Message,
+    schedule: String,
+) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+
+    if schedule.trim().is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "Your drafts are currently {}. Use /setschedule none, /setschedule next-free-slot or /setschedule 09:00 to change it.",
+                user.default_schedule().describe()
+            ),
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    match DefaultSchedule::parse(&schedule) {
+        Ok(default_schedule) => {
+            user.update_default_schedule(&db, default_schedule).await?;
+
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "New drafts will be {} (UTC{}).",
+                    default_schedule.describe(),
+                    describe_offset(user.utc_offset())
+                ),
+            )
+            .await?;
+        }
+        Err(e) => {
+            bot.send_message(
+                msg.chat.id,
+                format!("{}. Use none, next-free-slot or a time like 09:00.", e),
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn schedule_draft(
+    bot: Bot,
+    db: Arc<Database>,
+    msg: Message,
+    schedule: String,
+) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+
+    let draft = match msg.reply_to_message() {
+        Some(reply) => {
+            db.get_pending_draft_by_message(user.telegram_id, reply.id.0)
+                .await?
+        }
+        None => db.get_latest_pending_draft(user.telegram_id).await?,
+    };
+
+    let Some(draft) = draft else {
+        bot.send_message(msg.chat.id, "There is no pending draft to schedule.")
+            .await?;
+        return Ok(());
+    };
+
+    let schedule = match Schedule::parse(&schedule, OffsetDateTime::now_utc(), user.utc_offset()) {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "{}. Try /schedule next-free-slot, /schedule tomorrow 9:00 or /schedule 2024-12-24 18:00.",
+                    e
+                ),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    db.set_pending_draft_schedule(draft.id, schedule).await?;
+
+    let draft = PendingDraft {
+        schedule: Some(schedule.to_db_value()),
+        ..draft
+    };
+
+    if let Some(message_id) = draft.message_id {
+        bot.edit_message_text(
+            msg.chat.id,
+            MessageId(message_id as i32),
+            draft_preview(&draft, &user),
+        )
+        .reply_markup(review_keyboard(draft.id))
+        .await?;
+    }
+
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "Schedule updated: {}.",
+            schedule.describe(user.utc_offset())
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub async fn set_timezone(
+    bot: Bot,
+    db: Arc<Database>,
+    msg: Message,
+    offset: String,
+) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+
+    if offset.trim().is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "Your timezone is UTC{}. Use e.g. /timezone +02:00 to change it.",
+                describe_offset(user.utc_offset())
+            ),
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    match parse_utc_offset(&offset) {
+        Ok(offset) => {
+            user.update_utc_offset(&db, offset).await?;
+
+            bot.send_message(
+                msg.chat.id,
+                format!("Your timezone is now UTC{}.", describe_offset(offset)),
+            )
+            .await?;
+        }
+        Err(e) => {
+            bot.send_message(msg.chat.id, format!("{}.", e)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn set_destination(
+    bot: Bot,
+    db: Arc<Database>,
+    msg: Message,
+    destination: String,
+) -> HandlerResult {
+    let user = user_extractor(&bot, &db, &msg).await?;
+
+    let mut args = destination.split_whitespace();
+    let Some(destination) = args.next() else {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "Your posts currently go to {}. Use /destination typefully, /destination markdown or /destination webhook <url> to change it.",
+                user.destination()
+            ),
+        )
+        .await?;
+
+        return Ok(());
+    };
+
+    let destination = match destination.parse::<Destination>() {
+        Ok(destination) => destination,
+        Err(e) => {
+            bot.send_message(
+                msg.chat.id,
+                format!("{}. Use typefully, markdown or webhook <url>.", e),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let webhook_url = args.next();
+    if let Some(url) = webhook_url {
+        if let Err(e) = UrlPolicy::webhooks().check(url).await {
+            bot.send_message(msg.chat.id, e.to_string()).await?;
+            return Ok(());
+        }
+    }
+
+    if destination == Destination::Webhook && webhook_url.is_none() && user.webhook_url.is_none() {
+        bot.send_message(
+            msg.chat.id,
+            "Please provide the URL of your webhook, e.g. /destination webhook https://example.com/hook",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    user.update_destination(&db, destination, webhook_url)
+        .await?;
+
+    bot.send_message(
+        msg.chat.id,
+        format!("Approved posts will now be sent to {}.", destination),
     )
     .await?;
 
@@ -1,109 +1,500 @@
-use std::path::PathBuf;
-
-use anyhow::Context;
-use openai_api_rs::v1::{
-    api::OpenAIClient,
-    audio::{AudioTranscriptionRequest, WHISPER_1},
-    chat_completion::{self, ChatCompletionMessage, ChatCompletionRequest, MessageRole},
-    common::GPT4_O_MINI,
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+use crate::{
+    language::language_name,
+    providers::{CompletionRequest, Endpoint, Writer},
+    styles::StyleProfile,
+    thread,
 };
 
-const SUMMARY_INSTRUCTIONS: &str = r#"You are an expert for social media posts & working with texts in any language. Sometimes you get a text in German, English, Spanish or other languages.
+const REVISION_INSTRUCTIONS: &str = r#"You are an expert for social media posts & working with texts in any language.
 
-You get a text from a user and you should make a social media draft out of it.
-Your responses should ALWAYS be IN the language of the USERS TEXT.
+You get a social media post and a request from its author describing how the post should be changed.
+Your responses should ALWAYS be IN the language of the POST.
 
-Whenever you get a text you should do the following:
+Whenever you get a post you should do the following:
 
-1. Properly format the given text, making it readable, by adding appropriate commas, breaks etc.
-2. Make sure the post has a punchline.
-3. Don't use hashtags.
-4. Don't overdo it with emojis.
-5. Make sure the post is not too long.
-6. Make sure the post is not too short.
-7. Make sure the post is not too boring.
-8. Make sure you don't use typical AI words like: driven, motivated, inspired, delve, into the future 
+1. Apply the requested changes to the post.
+2. Keep everything the author didn't ask to change as it is.
+3. If the post is a thread, keep its tweets separated by four newlines.
+4. Only respond with the revised post, without any explanations.
 "#;
 
-const FORMAT_ONLY_INSTRUCTIONS: &str = r#"You are an expert for formatting text in any language. Sometimes you get a text in German, English, Spanish or other languages.
+const THREAD_INSTRUCTIONS: &str = r#"
+Split the post into a thread of tweets:
 
-You get a text from a user and you should format it for social media.
-Your responses should ALWAYS be IN the language of the USERS TEXT.
+1. Every tweet MUST have at most {max_length} characters.
+2. Every tweet should be readable on its own and lead into the next one.
+3. Don't number the tweets.
+4. Respond with a JSON object of the form {"tweets": ["first tweet", "second tweet"]}.
+"#;
 
-Whenever you get a text you should do the following:
+const EXAMPLES_INSTRUCTIONS: &str = r#"
+Here are some posts the user has written before. Match their voice, tone, length and formatting, but don't copy their content:
+"#;
 
-1. Properly format the given text, making it readable, by adding appropriate commas, breaks etc.
-2. Don't change the content or meaning of the text.
-3. Don't add or remove any information.
-4. Don't use hashtags.
-5. Don't add emojis.
-6. Keep the original tone and style of the text.
+const LANGUAGE_INSTRUCTIONS: &str = r#"
+Instead of answering in the language of the user's text, ALWAYS write the post in {language}. Translate the user's text if needed.
 "#;
 
-pub async fn transcribe_voice_note(path: PathBuf, api_key: String) -> anyhow::Result<String> {
-    // TODO: error handling and keeping the client static
-    let client = OpenAIClient::builder()
-        .with_api_key(api_key)
-        .build()
-        .ok()
-        .with_context(|| "")?;
+/// How many of a user's example posts are included in the prompt.
+pub const FEW_SHOT_EXAMPLES: i64 = 3;
+
+#[derive(Deserialize)]
+struct ThreadResponse {
+    tweets: Vec<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeyValidationError {
+    #[error("This OpenAI API key is invalid. Please check it and send it again.")]
+    InvalidKey,
+    #[error("This OpenAI API key has no quota left. Please check your plan and billing details at https://platform.openai.com/account/billing.")]
+    QuotaExhausted,
+    #[error("OpenAI rejected the key because of its organization: {0}")]
+    Organization(String),
+    #[error("OpenAI is rate limiting requests right now. Please try again in a moment.")]
+    RateLimited,
+    #[error("Could not validate the key with OpenAI: {0}")]
+    Other(String),
+}
+
+#[derive(Deserialize)]
+struct OpenAiErrorBody {
+    error: OpenAiErrorDetail,
+}
+
+#[derive(Deserialize)]
+struct OpenAiErrorDetail {
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    code: Option<String>,
+}
+
+/// Direct access to OpenAI endpoints that aren't covered by `openai-api-rs`.
+#[derive(Clone)]
+pub struct OpenAiApi {
+    base_url: String,
+    http: reqwest::Client,
+}
+
+impl OpenAiApi {
+    pub fn new(endpoint: &Endpoint) -> Self {
+        Self {
+            base_url: endpoint.base_url.trim_end_matches('/').to_string(),
+            http: endpoint.client(),
+        }
+    }
+
+    /// Checks a key by listing models, which doesn't cost anything.
+    pub async fn validate_key(&self, api_key: &str) -> Result<(), KeyValidationError> {
+        let response = self
+            .http
+            .get(format!("{}/models", self.base_url))
+            .bearer_auth(api_key)
+            .send()
+            .await
+            .map_err(|e| KeyValidationError::Other(e.to_string()))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        Err(key_rejection(status.as_u16(), body))
+    }
+}
+
+/// Explains why the models endpoint rejected a key.
+fn key_rejection(status: u16, body: String) -> KeyValidationError {
+    let (message, code) = match serde_json::from_str::<OpenAiErrorBody>(&body) {
+        Ok(body) => (body.error.message, body.error.code.unwrap_or_default()),
+        Err(_) => (body, String::new()),
+    };
+
+    match (status, code.as_str()) {
+        (_, "insufficient_quota") => KeyValidationError::QuotaExhausted,
+        (_, "no_organization" | "invalid_organization" | "mismatched_organization") => {
+            KeyValidationError::Organization(message)
+        }
+        (401, _) => KeyValidationError::InvalidKey,
+        (429, _) => KeyValidationError::RateLimited,
+        (status, _) => KeyValidationError::Other(format!("{} {}", status, message)),
+    }
+}
+
+/// How many words at the end of one segment are compared with the start of the next one.
+const MAX_SEAM_WORDS: usize = 40;
+
+/// Joins transcripts of overlapping segments, dropping the words that were transcribed twice
+/// at each seam.
+pub fn stitch_transcripts(parts: Vec<String>) -> String {
+    let mut words: Vec<String> = Vec::new();
 
-    let path = format!("{}", path.display());
-    dbg!(path.clone());
-    let req = AudioTranscriptionRequest::new(path, WHISPER_1.to_string());
+    for part in parts {
+        let next: Vec<String> = part.split_whitespace().map(str::to_string).collect();
+        let overlap = seam_overlap(&words, &next);
 
-    let result = client.audio_transcription(req).await?;
+        words.extend(next.into_iter().skip(overlap));
+    }
 
-    Ok(result.text)
+    words.join(" ")
+}
+
+/// Length of the longest run of words that ends `previous` and starts `next`, ignoring case
+/// and punctuation. Single word matches are ignored since they are most likely coincidence.
+fn seam_overlap(previous: &[String], next: &[String]) -> usize {
+    let normalize = |word: &String| {
+        word.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect::<String>()
+    };
+
+    let max = MAX_SEAM_WORDS.min(previous.len()).min(next.len());
+    let tail: Vec<String> = previous[previous.len() - max..]
+        .iter()
+        .map(normalize)
+        .collect();
+    let head: Vec<String> = next[..max].iter().map(normalize).collect();
+
+    (2..=max)
+        .rev()
+        .find(|&length| tail[max - length..] == head[..length])
+        .unwrap_or(0)
+}
+
+/// How a user wants their posts to be written.
+pub struct WritingOptions<'a> {
+    pub style: &'a StyleProfile,
+    pub examples: &'a [String],
+    /// Write the post in this language instead of the spoken one.
+    pub output_language: Option<&'a str>,
 }
 
 pub async fn make_summary(
-    from_user: String,
-    text: String,
-    api_key: String,
-    rewrite_enabled: bool,
+    writer: &dyn Writer,
+    from_user: &str,
+    text: &str,
+    options: &WritingOptions<'_>,
+    variants: i64,
+) -> anyhow::Result<Vec<String>> {
+    let instructions = instructions(options);
+
+    complete(writer, from_user, &instructions, text, variants).await
+}
+
+pub async fn revise_summary(
+    writer: &dyn Writer,
+    from_user: &str,
+    post: &str,
+    change_request: &str,
 ) -> anyhow::Result<String> {
-    let client = OpenAIClient::builder()
-        .with_api_key(api_key)
-        .build()
-        .ok()
-        .with_context(|| "")?;
-
-    let instructions = if rewrite_enabled {
-        SUMMARY_INSTRUCTIONS
-    } else {
-        FORMAT_ONLY_INSTRUCTIONS
-    };
+    let text = format!("Post:\n{}\n\nRequested changes:\n{}", post, change_request);
+
+    let mut revisions = complete(writer, from_user, REVISION_INSTRUCTIONS, &text, 1).await?;
+
+    Ok(revisions.remove(0))
+}
+
+pub async fn make_thread(
+    writer: &dyn Writer,
+    from_user: &str,
+    text: &str,
+    options: &WritingOptions<'_>,
+    max_length: usize,
+    variants: i64,
+) -> anyhow::Result<Vec<Vec<String>>> {
+    let instructions = format!(
+        "{}{}",
+        instructions(options),
+        THREAD_INSTRUCTIONS.replace("{max_length}", &max_length.to_string())
+    );
+
+    let choices = writer
+        .complete(&CompletionRequest {
+            from_user,
+            instructions: &instructions,
+            text,
+            variants,
+            json: true,
+        })
+        .await?;
+    if choices.is_empty() {
+        bail!("No thread available");
+    }
+
+    choices
+        .iter()
+        .map(|content| {
+            let thread: ThreadResponse =
+                serde_json::from_str(content).context("Thread response is not valid JSON")?;
+
+            Ok(thread::enforce_max_length(thread.tweets, max_length))
+        })
+        .collect()
+}
+
+fn instructions(options: &WritingOptions<'_>) -> String {
+    let mut instructions = with_examples(&options.style.instructions, options.examples);
+
+    if let Some(language) = options.output_language {
+        instructions
+            .push_str(&LANGUAGE_INSTRUCTIONS.replace("{language}", &language_name(language)));
+    }
+
+    instructions
+}
+
+/// Appends the user's example posts to the style instructions as few-shot examples.
+fn with_examples(instructions: &str, examples: &[String]) -> String {
+    if examples.is_empty() {
+        return instructions.to_string();
+    }
+
+    let examples = examples
+        .iter()
+        .map(|example| format!("<post>\n{}\n</post>", example.trim()))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    format!("{}{}\n{}\n", instructions, EXAMPLES_INSTRUCTIONS, examples)
+}
+
+/// Returns `variants` alternative completions, or a placeholder if the writer returned none.
+async fn complete(
+    writer: &dyn Writer,
+    from_user: &str,
+    instructions: &str,
+    text: &str,
+    variants: i64,
+) -> anyhow::Result<Vec<String>> {
+    let choices = writer
+        .complete(&CompletionRequest {
+            from_user,
+            instructions,
+            text,
+            variants,
+            json: false,
+        })
+        .await?;
+    if choices.is_empty() {
+        return Ok(vec!["No summary available".to_string()]);
+    }
+
+    Ok(choices)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+
+    use super::*;
+
+    /// Answers every request with canned completions and remembers what it was asked.
+    struct FakeWriter {
+        choices: Vec<String>,
+        requests: Mutex<Vec<(String, String, i64, bool)>>,
+    }
+
+    impl FakeWriter {
+        fn new(choices: &[&str]) -> Self {
+            Self {
+                choices: choices.iter().map(|choice| choice.to_string()).collect(),
+                requests: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn last_request(&self) -> (String, String, i64, bool) {
+            self.requests.lock().unwrap().last().cloned().unwrap()
+        }
+    }
+
+    #[async_trait]
+    impl Writer for FakeWriter {
+        async fn complete(&self, request: &CompletionRequest<'_>) -> anyhow::Result<Vec<String>> {
+            self.requests.lock().unwrap().push((
+                request.instructions.to_string(),
+                request.text.to_string(),
+                request.variants,
+                request.json,
+            ));
+
+            Ok(self.choices.clone())
+        }
+    }
+
+    fn style() -> StyleProfile {
+        StyleProfile::custom("test".to_string(), "Write a post.".to_string())
+    }
+
+    #[tokio::test]
+    async fn summary_includes_examples_and_output_language() {
+        let writer = FakeWriter::new(&["first", "second"]);
+        let style = style();
+        let examples = vec!["  An older post.  ".to_string()];
+        let options = WritingOptions {
+            style: &style,
+            examples: &examples,
+            output_language: Some("de"),
+        };
+
+        let posts = make_summary(&writer, "alice", "the note", &options, 2)
+            .await
+            .unwrap();
+
+        assert_eq!(posts, vec!["first", "second"]);
+        let (instructions, text, variants, json) = writer.last_request();
+        assert!(instructions.starts_with("Write a post."));
+        assert!(instructions.contains("<post>\nAn older post.\n</post>"));
+        assert!(instructions.contains(&language_name("de")));
+        assert_eq!(text, "the note");
+        assert_eq!(variants, 2);
+        assert!(!json);
+    }
+
+    #[tokio::test]
+    async fn summary_falls_back_to_placeholder() {
+        let writer = FakeWriter::new(&[]);
+        let style = style();
+        let options = WritingOptions {
+            style: &style,
+            examples: &[],
+            output_language: None,
+        };
+
+        let posts = make_summary(&writer, "alice", "the note", &options, 1)
+            .await
+            .unwrap();
+
+        assert_eq!(posts, vec!["No summary available"]);
+        assert_eq!(writer.last_request().0, "Write a post.");
+    }
+
+    #[tokio::test]
+    async fn revision_sends_post_and_change_request() {
+        let writer = FakeWriter::new(&["revised"]);
+
+        let revised = revise_summary(&writer, "alice", "the post", "shorter")
+            .await
+            .unwrap();
+
+        assert_eq!(revised, "revised");
+        let (instructions, text, variants, _) = writer.last_request();
+        assert_eq!(instructions, REVISION_INSTRUCTIONS);
+        assert_eq!(text, "Post:\nthe post\n\nRequested changes:\nshorter");
+        assert_eq!(variants, 1);
+    }
+
+    #[tokio::test]
+    async fn thread_is_parsed_and_kept_within_length() {
+        let writer = FakeWriter::new(&[
+            r#"{"tweets": ["Short one.", "This tweet is too long. It gets split."]}"#,
+        ]);
+        let style = style();
+        let options = WritingOptions {
+            style: &style,
+            examples: &[],
+            output_language: None,
+        };
+
+        let threads = make_thread(&writer, "alice", "the note", &options, 25, 1)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            threads,
+            vec![vec![
+                "Short one.".to_string(),
+                "This tweet is too long.".to_string(),
+                "It gets split.".to_string(),
+            ]]
+        );
+        let (instructions, _, _, json) = writer.last_request();
+        assert!(instructions.contains("at most 25 characters"));
+        assert!(json);
+    }
+
+    #[tokio::test]
+    async fn thread_rejects_invalid_json() {
+        let writer = FakeWriter::new(&["not json"]);
+        let style = style();
+        let options = WritingOptions {
+            style: &style,
+            examples: &[],
+            output_language: None,
+        };
+
+        assert!(make_thread(&writer, "alice", "the note", &options, 280, 1)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn stitching_drops_repeated_words_at_seams() {
+        let parts = vec![
+            "We talked about the new release today".to_string(),
+            "the new release today, and what comes next".to_string(),
+        ];
+
+        assert_eq!(
+            stitch_transcripts(parts),
+            "We talked about the new release today and what comes next"
+        );
+    }
+
+    #[test]
+    fn explains_key_rejections() {
+        let body = |code: &str, message: &str| {
+            format!(
+                r#"{{"error": {{"message": "{}", "type": "invalid_request_error", "code": "{}"}}}}"#,
+                message, code
+            )
+        };
+
+        assert!(matches!(
+            key_rejection(429, body("insufficient_quota", "You exceeded your quota.")),
+            KeyValidationError::QuotaExhausted
+        ));
+        for code in [
+            "no_organization",
+            "invalid_organization",
+            "mismatched_organization",
+        ] {
+            match key_rejection(401, body(code, "No such organization.")) {
+                KeyValidationError::Organization(message) => {
+                    assert_eq!(message, "No such organization.")
+                }
+                other => panic!("{}: {:?}", code, other),
+            }
+        }
+        assert!(matches!(
+            key_rejection(401, body("invalid_api_key", "Incorrect API key provided.")),
+            KeyValidationError::InvalidKey
+        ));
+        assert!(matches!(
+            key_rejection(401, "Unauthorized".to_string()),
+            KeyValidationError::InvalidKey
+        ));
+        assert!(matches!(
+            key_rejection(429, body("rate_limit_exceeded", "Slow down.")),
+            KeyValidationError::RateLimited
+        ));
 
-    let msgs = vec![
-        ChatCompletionMessage {
-            role: MessageRole::system,
-            content: chat_completion::Content::Text(instructions.to_string()),
-            name: None,
-            tool_calls: None,
-            tool_call_id: None,
-        },
-        ChatCompletionMessage {
-            role: MessageRole::user,
-            content: chat_completion::Content::Text(text),
-            name: Some(from_user),
-            tool_calls: None,
-            tool_call_id: None,
-        },
-    ];
-
-    let req = ChatCompletionRequest::new(GPT4_O_MINI.to_string(), msgs);
-
-    let result = client.chat_completion(req).await?;
-
-    let last_msg = result.choices.last();
-
-    match last_msg {
-        None => Ok("No summary available".to_string()),
-        Some(last_msg) => Ok(last_msg
-            .message
-            .content
-            .clone()
-            .unwrap_or("No content".to_string())),
+        match key_rejection(503, "Service Unavailable".to_string()) {
+            KeyValidationError::Other(message) => assert_eq!(message, "503 Service Unavailable"),
+            other => panic!("{:?}", other),
+        }
+        match key_rejection(500, body("", "The server had an error.")) {
+            KeyValidationError::Other(message) => {
+                assert_eq!(message, "500 The server had an error.")
+            }
+            other => panic!("{:?}", other),
+        }
     }
 }
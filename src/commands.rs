@@ -1,16 +1,19 @@
+use serde::{Deserialize, Serialize};
 use teloxide::{
-    dispatching::{
-        dialogue::{self, InMemStorage},
-        UpdateHandler,
-    },
+    dispatching::{dialogue, UpdateHandler},
     macros::BotCommands,
     prelude::*,
     types::{InlineKeyboardButton, InlineKeyboardMarkup},
 };
 
-use crate::actions;
+use crate::{
+    actions,
+    db::{ArchivedTranscript, Database, Idea, StyleExample},
+    media::Media,
+    publishers::Published,
+};
 
-pub type BotDialogue = Dialogue<State, InMemStorage<State>>;
+pub type BotDialogue = Dialogue<State, Database>;
 
 #[derive(BotCommands, Clone, PartialEq, Eq, Debug)]
 #[command(
@@ -26,23 +29,121 @@ pub enum BotCommand {
     SetApiKey,
     #[command(description = "Set or update your Typefully API key")]
     SetTypefullyKey,
+    #[command(description = "Show which API keys are configured")]
+    Keys,
     #[command(description = "Check your remaining free usage")]
     Usage,
     #[command(description = "Toggle between AI rewriting and simple formatting")]
     ToggleRewrite,
+    #[command(description = "List your style profiles")]
+    Styles,
+    #[command(
+        description = "Select the style for new posts, e.g. /style dry-technical",
+        parse_with = "default"
+    )]
+    Style(String),
+    #[command(description = "Create your own style profile", parse_with = "default")]
+    NewStyle(String),
+    #[command(
+        description = "Change one of your style profiles",
+        parse_with = "default"
+    )]
+    EditStyle(String),
+    #[command(
+        description = "Delete one of your style profiles",
+        parse_with = "default"
+    )]
+    DeleteStyle(String),
+    #[command(description = "Import your recently published Typefully posts as writing examples")]
+    ImportPosts,
+    #[command(
+        description = "Add a post you wrote as a writing example",
+        parse_with = "default"
+    )]
+    AddExample(String),
+    #[command(description = "Show and delete your writing examples")]
+    Examples,
+    #[command(
+        description = "Set how many variants to write per note, e.g. /variants 3",
+        parse_with = "default"
+    )]
+    Variants(String),
+    #[command(
+        description = "Use an OpenAI-compatible server with your own key: <base url> or default",
+        parse_with = "default"
+    )]
+    Provider(String),
+    #[command(
+        description = "Choose your models, e.g. /model chat gpt-4o or /model transcription whisper-1",
+        parse_with = "default"
+    )]
+    Model(String),
+    #[command(
+        description = "Set the spoken and post language, e.g. /language spoken de or /language post en",
+        parse_with = "default"
+    )]
+    Language(String),
+    #[command(description = "Toggle splitting long posts into a thread")]
+    ToggleThread,
+    #[command(
+        description = "Set your default schedule: none, next-free-slot or a daily time like 09:00",
+        parse_with = "default"
+    )]
+    SetSchedule(String),
+    #[command(
+        description = "Schedule the pending draft, e.g. /schedule tomorrow 9:00",
+        parse_with = "default"
+    )]
+    Schedule(String),
+    #[command(
+        description = "Set your timezone as UTC offset, e.g. /timezone +02:00",
+        parse_with = "default"
+    )]
+    Timezone(String),
+    #[command(
+        description = "Choose where posts go: typefully, markdown or webhook <url>",
+        parse_with = "default"
+    )]
+    Destination(String),
+    #[command(description = "Collect several notes and write a single post from them")]
+    Collect,
+    #[command(description = "Write the post from the collected notes")]
+    Done,
+    #[command(description = "Toggle creating public share links for Typefully drafts")]
+    ToggleShare,
+    #[command(description = "Toggle saving notes as ideas instead of drafting posts")]
+    ToggleInbox,
+    #[command(description = "List your ideas and turn them into posts")]
+    Ideas,
+    #[command(description = "Browse your past transcripts and posts")]
+    History,
+    #[command(
+        description = "Search your past transcripts, e.g. /search pricing idea",
+        parse_with = "default"
+    )]
+    Search(String),
     #[command(description = "Start using the bot")]
     Start,
     #[command(description = "Delete your account and all data")]
     DeleteAccount,
 }
 
-#[derive(Clone, Default)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub enum State {
     #[default]
     Start,
     WaitingForTypefullyApiKey,
     WaitingForOpenAiApiKey,
     WaitingForDeleteConfirmation,
+    WaitingForStyleInstructions {
+        name: String,
+    },
+    WaitingForExample,
+    /// Transcripts of the notes sent since /collect, in order.
+    Collecting {
+        notes: Vec<String>,
+        language: Option<String>,
+    },
     // Registered {
     //     user: User,
     // },
@@ -50,6 +151,120 @@ pub enum State {
     // Transcribing(String),
 }
 
+/// Splits callback data like `approve:42` into the action and the id it applies to.
+fn parse_callback(data: &str) -> Option<(&str, i64)> {
+    let (action, id) = data.split_once(':')?;
+
+    Some((action, id.parse().ok()?))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DraftAction {
+    Approve(i64),
+    Regenerate(i64),
+    Schedule(i64),
+    Discard(i64),
+    Pick(i64),
+}
+
+impl DraftAction {
+    pub fn from_callback(q: CallbackQuery) -> Option<Self> {
+        let data = q.data?;
+        let (action, id) = parse_callback(&data)?;
+
+        match action {
+            "approve" => Some(Self::Approve(id)),
+            "regenerate" => Some(Self::Regenerate(id)),
+            "schedule" => Some(Self::Schedule(id)),
+            "discard" => Some(Self::Discard(id)),
+            "pick" => Some(Self::Pick(id)),
+            _ => None,
+        }
+    }
+
+    fn callback_data(&self) -> String {
+        match self {
+            Self::Approve(id) => format!("approve:{}", id),
+            Self::Regenerate(id) => format!("regenerate:{}", id),
+            Self::Schedule(id) => format!("schedule:{}", id),
+            Self::Discard(id) => format!("discard:{}", id),
+            Self::Pick(id) => format!("pick:{}", id),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExampleAction {
+    Delete(i64),
+}
+
+impl ExampleAction {
+    pub fn from_callback(q: CallbackQuery) -> Option<Self> {
+        let data = q.data?;
+        let (action, id) = parse_callback(&data)?;
+
+        match action {
+            "delete-example" => Some(Self::Delete(id)),
+            _ => None,
+        }
+    }
+
+    fn callback_data(&self) -> String {
+        match self {
+            Self::Delete(id) => format!("delete-example:{}", id),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HistoryAction {
+    Page(i64),
+    Show(i64),
+}
+
+impl HistoryAction {
+    pub fn from_callback(q: CallbackQuery) -> Option<Self> {
+        let data = q.data?;
+        let (action, id) = parse_callback(&data)?;
+
+        match action {
+            "history" => Some(Self::Page(id)),
+            "transcript" => Some(Self::Show(id)),
+            _ => None,
+        }
+    }
+
+    fn callback_data(&self) -> String {
+        match self {
+            Self::Page(page) => format!("history:{}", page),
+            Self::Show(id) => format!("transcript:{}", id),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IdeaAction {
+    Draft(i64),
+}
+
+impl IdeaAction {
+    pub fn from_callback(q: CallbackQuery) -> Option<Self> {
+        let data = q.data?;
+        let (action, id) = parse_callback(&data)?;
+
+        match action {
+            "draft-idea" => Some(Self::Draft(id)),
+            _ => None,
+        }
+    }
+
+    fn callback_data(&self) -> String {
+        match self {
+            Self::Draft(id) => format!("draft-idea:{}", id),
+        }
+    }
+}
+
 pub fn bot_schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
     use dptree::case;
 
@@ -58,8 +273,33 @@ pub fn bot_schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + '
         .branch(case![BotCommand::Start].endpoint(actions::start))
         .branch(case![BotCommand::SetApiKey].endpoint(actions::set_api_key))
         .branch(case![BotCommand::SetTypefullyKey].endpoint(actions::set_typefully_key))
+        .branch(case![BotCommand::Keys].endpoint(actions::keys))
         .branch(case![BotCommand::Usage].endpoint(actions::usage))
         .branch(case![BotCommand::ToggleRewrite].endpoint(actions::toggle_rewrite))
+        .branch(case![BotCommand::Styles].endpoint(actions::list_styles))
+        .branch(case![BotCommand::Style(name)].endpoint(actions::select_style))
+        .branch(case![BotCommand::NewStyle(name)].endpoint(actions::new_style))
+        .branch(case![BotCommand::EditStyle(name)].endpoint(actions::edit_style))
+        .branch(case![BotCommand::DeleteStyle(name)].endpoint(actions::delete_style))
+        .branch(case![BotCommand::ImportPosts].endpoint(actions::import_posts))
+        .branch(case![BotCommand::AddExample(example)].endpoint(actions::add_example))
+        .branch(case![BotCommand::Examples].endpoint(actions::list_examples))
+        .branch(case![BotCommand::Variants(variants)].endpoint(actions::set_variants))
+        .branch(case![BotCommand::Provider(provider)].endpoint(actions::set_provider))
+        .branch(case![BotCommand::Model(model)].endpoint(actions::set_model))
+        .branch(case![BotCommand::Language(language)].endpoint(actions::set_language))
+        .branch(case![BotCommand::ToggleThread].endpoint(actions::toggle_thread))
+        .branch(case![BotCommand::SetSchedule(schedule)].endpoint(actions::set_default_schedule))
+        .branch(case![BotCommand::Schedule(schedule)].endpoint(actions::schedule_draft))
+        .branch(case![BotCommand::Timezone(offset)].endpoint(actions::set_timezone))
+        .branch(case![BotCommand::Destination(destination)].endpoint(actions::set_destination))
+        .branch(case![BotCommand::Collect].endpoint(actions::start_collecting))
+        .branch(case![BotCommand::Done].endpoint(actions::finish_collecting))
+        .branch(case![BotCommand::ToggleShare].endpoint(actions::toggle_share))
+        .branch(case![BotCommand::ToggleInbox].endpoint(actions::toggle_inbox))
+        .branch(case![BotCommand::Ideas].endpoint(actions::list_ideas))
+        .branch(case![BotCommand::History].endpoint(actions::history))
+        .branch(case![BotCommand::Search(query)].endpoint(actions::search))
         .branch(case![BotCommand::DeleteAccount].endpoint(actions::delete_account));
 
     let message_handler = Update::filter_message()
@@ -72,11 +312,46 @@ pub fn bot_schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + '
             case![State::WaitingForDeleteConfirmation]
                 .endpoint(actions::handle_delete_confirmation),
         )
-        .branch(Message::filter_voice().endpoint(actions::handle_voice_note))
+        .branch(
+            case![State::WaitingForStyleInstructions { name }]
+                .endpoint(actions::receive_style_instructions),
+        )
+        .branch(case![State::WaitingForExample].endpoint(actions::receive_example))
+        .branch(
+            case![State::Collecting { notes, language }]
+                .branch(dptree::filter_map(Media::from_message).endpoint(actions::collect_media))
+                .branch(Message::filter_text().endpoint(actions::collect_text)),
+        )
+        .branch(
+            dptree::filter_map_async(actions::replied_draft).endpoint(actions::handle_draft_reply),
+        )
+        .branch(dptree::filter_map(Media::from_message).endpoint(actions::handle_voice_note))
+        .branch(
+            Message::filter_text()
+                .filter(|text: String| !text.starts_with('/'))
+                .endpoint(actions::handle_text_note),
+        )
         .branch(dptree::endpoint(actions::invalid_state));
 
-    dialogue::enter::<Update, InMemStorage<State>, _, _>()
+    let callback_handler = Update::filter_callback_query()
+        .branch(
+            dptree::filter_map(DraftAction::from_callback).endpoint(actions::handle_draft_action),
+        )
+        .branch(
+            dptree::filter_map(ExampleAction::from_callback)
+                .endpoint(actions::handle_example_action),
+        )
+        .branch(
+            dptree::filter_map(HistoryAction::from_callback)
+                .endpoint(actions::handle_history_action),
+        )
+        .branch(
+            dptree::filter_map(IdeaAction::from_callback).endpoint(actions::handle_idea_action),
+        );
+
+    dialogue::enter::<Update, Database, State, _>()
         .branch(message_handler)
+        .branch(callback_handler)
         .branch(Update::filter_inline_query().endpoint(actions::inline_query_handler))
 }
 
@@ -85,3 +360,119 @@ pub fn keyboard() -> InlineKeyboardMarkup {
         "Credits", "credits",
     )]])
 }
+
+pub fn review_keyboard(draft_id: i64) -> InlineKeyboardMarkup {
+    let button = |label: &str, action: DraftAction| {
+        InlineKeyboardButton::callback(label, action.callback_data())
+    };
+
+    InlineKeyboardMarkup::new(vec![
+        vec![
+            button("✅ Approve", DraftAction::Approve(draft_id)),
+            button("🔄 Regenerate", DraftAction::Regenerate(draft_id)),
+            button("🗑 Discard", DraftAction::Discard(draft_id)),
+        ],
+        vec![button("📅 Next free slot", DraftAction::Schedule(draft_id))],
+    ])
+}
+
+pub fn variant_keyboard(draft_id: i64, number: usize) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::callback(
+        format!("✅ Use variant {}", number),
+        DraftAction::Pick(draft_id).callback_data(),
+    )]])
+}
+
+/// One delete button per example, numbered like the list in the message.
+pub fn examples_keyboard(examples: &[StyleExample]) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(numbered_buttons(
+        examples.iter().map(|example| example.id),
+        "🗑",
+        |id| ExampleAction::Delete(id).callback_data(),
+    ))
+}
+
+/// Buttons to open a published draft, if the destination returned links to it.
+pub fn published_keyboard(published: &Published) -> Option<InlineKeyboardMarkup> {
+    let buttons = [
+        ("✏️ Open draft", &published.edit_url),
+        ("🔗 Share link", &published.share_url),
+    ]
+    .into_iter()
+    .filter_map(|(label, url)| {
+        Some(InlineKeyboardButton::url(
+            label,
+            url.as_ref()?.parse().ok()?,
+        ))
+    })
+    .collect::<Vec<_>>();
+
+    (!buttons.is_empty()).then(|| InlineKeyboardMarkup::new(vec![buttons]))
+}
+
+/// One button per idea to draft a post from it, numbered like the list in the message.
+pub fn ideas_keyboard(ideas: &[Idea]) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(numbered_buttons(
+        ideas.iter().map(|idea| idea.id),
+        "✍️",
+        |id| IdeaAction::Draft(id).callback_data(),
+    ))
+}
+
+/// One button per transcript to show it in full, numbered like the list in the message.
+pub fn search_keyboard(transcripts: &[ArchivedTranscript]) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(transcript_buttons(transcripts))
+}
+
+/// Like [`search_keyboard`], with buttons to page through newer and older transcripts.
+pub fn history_keyboard(
+    transcripts: &[ArchivedTranscript],
+    page: i64,
+    has_older: bool,
+) -> InlineKeyboardMarkup {
+    let mut rows = transcript_buttons(transcripts);
+
+    let mut navigation = Vec::new();
+    if page > 0 {
+        navigation.push(InlineKeyboardButton::callback(
+            "◀ Newer",
+            HistoryAction::Page(page - 1).callback_data(),
+        ));
+    }
+    if has_older {
+        navigation.push(InlineKeyboardButton::callback(
+            "Older ▶",
+            HistoryAction::Page(page + 1).callback_data(),
+        ));
+    }
+    if !navigation.is_empty() {
+        rows.push(navigation);
+    }
+
+    InlineKeyboardMarkup::new(rows)
+}
+
+fn transcript_buttons(transcripts: &[ArchivedTranscript]) -> Vec<Vec<InlineKeyboardButton>> {
+    numbered_buttons(
+        transcripts.iter().map(|transcript| transcript.id),
+        "📄",
+        |id| HistoryAction::Show(id).callback_data(),
+    )
+}
+
+/// One button per id, labeled like the numbered list in the message, five per row.
+fn numbered_buttons(
+    ids: impl IntoIterator<Item = i64>,
+    icon: &str,
+    callback_data: impl Fn(i64) -> String,
+) -> Vec<Vec<InlineKeyboardButton>> {
+    let buttons = ids
+        .into_iter()
+        .enumerate()
+        .map(|(index, id)| {
+            InlineKeyboardButton::callback(format!("{} {}", icon, index + 1), callback_data(id))
+        })
+        .collect::<Vec<_>>();
+
+    buttons.chunks(5).map(|row| row.to_vec()).collect()
+}
@@ -0,0 +1,320 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use sha2::{Digest, Sha256};
+
+const PREFIX: &str = "enc:v1";
+const NONCE_LEN: usize = 12;
+
+/// Master keys used for envelope encryption of secrets stored in the database.
+///
+/// Every value is encrypted with its own random data key, which in turn is encrypted
+/// ("wrapped") with the current master key. Rotating the master key only requires
+/// re-wrapping the data keys.
+pub struct Keyring {
+    current: MasterKey,
+    previous: Vec<MasterKey>,
+}
+
+struct MasterKey {
+    id: String,
+    cipher: Aes256Gcm,
+}
+
+impl MasterKey {
+    fn from_base64(encoded: &str) -> Result<Self> {
+        let bytes = BASE64
+            .decode(encoded.trim())
+            .context("Encryption key is not valid base64")?;
+        if bytes.len() != 32 {
+            bail!("Encryption key must be 32 bytes, got {}", bytes.len());
+        }
+
+        let id = Sha256::digest(&bytes)[..4]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        Ok(Self {
+            id,
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&bytes)),
+        })
+    }
+}
+
+impl Keyring {
+    /// Reads the master key from `ENCRYPTION_KEY` and retired keys from the comma separated
+    /// `ENCRYPTION_KEY_PREVIOUS`.
+    pub fn from_env() -> Result<Self> {
+        let current = std::env::var("ENCRYPTION_KEY")
+            .context("ENCRYPTION_KEY is not set. Generate one with `openssl rand -base64 32`.")?;
+        let previous = std::env::var("ENCRYPTION_KEY_PREVIOUS").unwrap_or_default();
+        let previous: Vec<&str> = previous
+            .split(',')
+            .filter(|key| !key.trim().is_empty())
+            .collect();
+
+        Self::new(&current, &previous)
+    }
+
+    /// Builds a keyring from base64 encoded 32 byte keys.
+    pub fn new(current: &str, previous: &[&str]) -> Result<Self> {
+        Ok(Self {
+            current: MasterKey::from_base64(current)?,
+            previous: previous
+                .iter()
+                .map(|key| MasterKey::from_base64(key))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> Result<String> {
+        let data_key = Aes256Gcm::generate_key(OsRng);
+        let data_cipher = Aes256Gcm::new(&data_key);
+
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = data_cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt secret"))?;
+
+        let wrapped_key = self.wrap(&data_key)?;
+
+        Ok(format!(
+            "{}:{}:{}:{}",
+            PREFIX,
+            self.current.id,
+            BASE64.encode(wrapped_key),
+            BASE64.encode([nonce.as_slice(), &ciphertext].concat())
+        ))
+    }
+
+    /// Decrypts a stored value. Values without the encryption prefix are returned as is, so
+    /// rows written before encryption was introduced keep working until they are migrated.
+    pub fn decrypt(&self, value: &str) -> Result<String> {
+        let Some(envelope) = Envelope::parse(value)? else {
+            return Ok(value.to_string());
+        };
+
+        let data_cipher = Aes256Gcm::new(&self.unwrap(&envelope)?);
+        let (nonce, ciphertext) = split_nonce(&envelope.payload)?;
+        let plaintext = data_cipher
+            .decrypt(nonce, ciphertext)
+            .map_err(|_| anyhow::anyhow!("Failed to decrypt secret"))?;
+
+        Ok(String::from_utf8(plaintext)?)
+    }
+
+    /// Whether a stored value is plaintext or wrapped with a retired master key.
+    pub fn needs_rotation(&self, value: &str) -> bool {
+        match Envelope::parse(value) {
+            Ok(Some(envelope)) => envelope.key_id != self.current.id,
+            Ok(None) => true,
+            Err(_) => false,
+        }
+    }
+
+    /// Whether a stored value has the encryption prefix but can't be parsed, so it can never be
+    /// decrypted.
+    pub fn is_malformed(&self, value: &str) -> bool {
+        Envelope::parse(value).is_err()
+    }
+
+    /// Encrypts plaintext values and re-wraps data keys of values encrypted with a retired
+    /// master key, leaving the encrypted payload untouched.
+    pub fn rotate(&self, value: &str) -> Result<String> {
+        let Some(envelope) = Envelope::parse(value)? else {
+            return self.encrypt(value);
+        };
+
+        let data_key = self.unwrap(&envelope)?;
+
+        Ok(format!(
+            "{}:{}:{}:{}",
+            PREFIX,
+            self.current.id,
+            BASE64.encode(self.wrap(&data_key)?),
+            BASE64.encode(&envelope.payload)
+        ))
+    }
+
+    fn wrap(&self, data_key: &Key<Aes256Gcm>) -> Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let wrapped = self
+            .current
+            .cipher
+            .encrypt(&nonce, data_key.as_slice())
+            .map_err(|_| anyhow::anyhow!("Failed to wrap data key"))?;
+
+        Ok([nonce.as_slice(), &wrapped].concat())
+    }
+
+    fn unwrap(&self, envelope: &Envelope) -> Result<Key<Aes256Gcm>> {
+        let master_key = std::iter::once(&self.current)
+            .chain(&self.previous)
+            .find(|key| key.id == envelope.key_id)
+            .with_context(|| format!("Unknown encryption key {}", envelope.key_id))?;
+
+        let (nonce, wrapped) = split_nonce(&envelope.wrapped_key)?;
+        let data_key = master_key
+            .cipher
+            .decrypt(nonce, wrapped)
+            .map_err(|_| anyhow::anyhow!("Failed to unwrap data key"))?;
+
+        Ok(*Key::<Aes256Gcm>::from_slice(&data_key))
+    }
+}
+
+/// Shows just enough of a secret to recognize it, e.g. `sk-…a3F9`.
+pub fn mask_secret(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+
+    if chars.len() < 12 {
+        return format!(
+            "…{}",
+            chars[chars.len().saturating_sub(2)..]
+                .iter()
+                .collect::<String>()
+        );
+    }
+
+    format!(
+        "{}…{}",
+        chars[..3].iter().collect::<String>(),
+        chars[chars.len() - 4..].iter().collect::<String>()
+    )
+}
+
+struct Envelope {
+    key_id: String,
+    wrapped_key: Vec<u8>,
+    payload: Vec<u8>,
+}
+
+impl Envelope {
+    fn parse(value: &str) -> Result<Option<Self>> {
+        let Some(rest) = value
+            .strip_prefix(PREFIX)
+            .and_then(|rest| rest.strip_prefix(':'))
+        else {
+            return Ok(None);
+        };
+
+        let mut parts = rest.splitn(3, ':');
+        let (Some(key_id), Some(wrapped_key), Some(payload)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            bail!("Malformed encrypted value");
+        };
+
+        Ok(Some(Self {
+            key_id: key_id.to_string(),
+            wrapped_key: BASE64.decode(wrapped_key)?,
+            payload: BASE64.decode(payload)?,
+        }))
+    }
+}
+
+fn split_nonce(bytes: &[u8]) -> Result<(&Nonce<<Aes256Gcm as AeadCore>::NonceSize>, &[u8])> {
+    if bytes.len() < NONCE_LEN {
+        bail!("Encrypted value is too short");
+    }
+
+    let (nonce, rest) = bytes.split_at(NONCE_LEN);
+    Ok((Nonce::from_slice(nonce), rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: u8) -> String {
+        BASE64.encode([byte; 32])
+    }
+
+    fn keyring(current: u8, previous: &[u8]) -> Keyring {
+        let previous: Vec<String> = previous.iter().map(|&byte| key(byte)).collect();
+        let previous: Vec<&str> = previous.iter().map(String::as_str).collect();
+
+        Keyring::new(&key(current), &previous).unwrap()
+    }
+
+    #[test]
+    fn round_trips_secrets() {
+        let keyring = keyring(1, &[]);
+
+        let encrypted = keyring.encrypt("sk-secret").unwrap();
+
+        assert!(encrypted.starts_with("enc:v1:"));
+        assert!(!encrypted.contains("sk-secret"));
+        assert_eq!(keyring.decrypt(&encrypted).unwrap(), "sk-secret");
+        assert_ne!(keyring.encrypt("sk-secret").unwrap(), encrypted);
+    }
+
+    #[test]
+    fn passes_plaintext_through() {
+        assert_eq!(keyring(1, &[]).decrypt("sk-plain").unwrap(), "sk-plain");
+    }
+
+    #[test]
+    fn rotates_from_previous_master_key() {
+        let encrypted = keyring(1, &[]).encrypt("sk-secret").unwrap();
+        let keyring = keyring(2, &[1]);
+
+        let rotated = keyring.rotate(&encrypted).unwrap();
+
+        assert_ne!(rotated, encrypted);
+        assert!(!keyring.needs_rotation(&rotated));
+        assert_eq!(keyring.decrypt(&rotated).unwrap(), "sk-secret");
+        // Only the data key is re-wrapped, the payload stays the same
+        assert_eq!(rotated.rsplit(':').next(), encrypted.rsplit(':').next());
+    }
+
+    #[test]
+    fn detects_values_needing_rotation() {
+        let old = keyring(1, &[]).encrypt("sk-secret").unwrap();
+        let keyring = keyring(2, &[1]);
+        let current = keyring.encrypt("sk-secret").unwrap();
+
+        assert!(keyring.needs_rotation("sk-plain"));
+        assert!(keyring.needs_rotation(&old));
+        assert!(!keyring.needs_rotation(&current));
+        assert!(!keyring.needs_rotation("enc:v1:broken"));
+    }
+
+    #[test]
+    fn fails_for_unknown_master_key() {
+        let encrypted = keyring(1, &[]).encrypt("sk-secret").unwrap();
+
+        let error = keyring(2, &[]).decrypt(&encrypted).unwrap_err();
+
+        assert!(error.to_string().starts_with("Unknown encryption key"));
+    }
+
+    #[test]
+    fn detects_malformed_values() {
+        let keyring = keyring(1, &[]);
+
+        assert!(keyring.is_malformed("enc:v1:broken"));
+        assert!(keyring.is_malformed("enc:v1:abcd:not base64:payload"));
+        assert!(keyring.decrypt("enc:v1:broken").is_err());
+        assert!(!keyring.is_malformed("sk-plain"));
+        assert!(!keyring.is_malformed(&keyring.encrypt("sk-secret").unwrap()));
+    }
+
+    #[test]
+    fn rejects_keys_of_wrong_length() {
+        assert!(MasterKey::from_base64(&BASE64.encode([1; 16])).is_err());
+        assert!(MasterKey::from_base64("not base64!").is_err());
+    }
+
+    #[test]
+    fn masks_secrets() {
+        assert_eq!(mask_secret("sk-1234567890abcdef"), "sk-…cdef");
+        assert_eq!(mask_secret("short"), "…rt");
+        assert_eq!(mask_secret("x"), "…x");
+        assert_eq!(mask_secret(""), "…");
+    }
+}
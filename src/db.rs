@@ -1,10 +1,21 @@
+use std::fmt;
+
 use anyhow::Result;
-use sqlx::{sqlite::SqlitePool, Pool, Sqlite};
+use log::{error, info};
+use sqlx::{sqlite::SqlitePool, Executor, Pool, Sqlite};
 use teloxide::types::Chat;
-use time::OffsetDateTime;
+use time::{OffsetDateTime, UtcOffset};
+
+use crate::{
+    crypto::Keyring,
+    publishers::{Destination, Published},
+    schedule::{offset_from_minutes, DefaultSchedule, Schedule},
+    styles::{StyleProfile, DEFAULT_STYLE},
+};
 
 pub struct Database {
     pool: Pool<Sqlite>,
+    keyring: Keyring,
 }
 
 pub struct UserPayload {
@@ -12,19 +23,114 @@ pub struct UserPayload {
     pub name: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct User {
     pub telegram_id: i64,
     pub username: String,
     pub typefully_api_key: Option<String>,
     pub openai_api_key: Option<String>,
-    pub rewrite_enabled: bool,
+    pub style_profile: String,
+    #[allow(unused)]
+    pub created_at: OffsetDateTime,
+    pub default_schedule: Option<String>,
+    pub utc_offset_minutes: i64,
+    pub thread_enabled: bool,
+    pub destination: String,
+    pub webhook_url: Option<String>,
+    pub variant_count: i64,
+    pub ai_base_url: Option<String>,
+    pub chat_model: Option<String>,
+    pub transcription_model: Option<String>,
+    pub transcription_language: Option<String>,
+    pub output_language: Option<String>,
+    pub inbox_enabled: bool,
+    pub share_enabled: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct PendingDraft {
+    pub id: i64,
+    #[allow(unused)]
+    pub telegram_id: i64,
+    pub transcript: String,
+    pub content: String,
+    #[allow(unused)]
+    pub created_at: OffsetDateTime,
+    #[allow(unused)]
+    pub message_id: Option<i64>,
+    pub schedule: Option<String>,
+    /// Set while the draft is one of several variants the user hasn't picked from yet.
+    pub variant_group: Option<i64>,
+    /// The language spoken in the transcript, if known.
+    pub language: Option<String>,
+    /// The archived transcript the draft was written from.
+    pub transcript_id: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchivedTranscript {
+    pub id: i64,
+    #[allow(unused)]
+    pub telegram_id: i64,
+    pub transcript: String,
+    /// The post written from the transcript, if any.
+    pub post: Option<String>,
+    pub created_at: OffsetDateTime,
+    /// Link to edit the Typefully draft the post was sent to.
+    pub typefully_url: Option<String>,
+    /// Public link to the draft, if sharing was enabled.
+    pub typefully_share_url: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Idea {
+    pub id: i64,
+    #[allow(unused)]
+    pub telegram_id: i64,
+    pub transcript: String,
+    pub language: Option<String>,
+    /// Hashtags from the caption, separated by spaces.
+    pub tags: String,
+    pub created_at: OffsetDateTime,
+    /// The archived transcript the idea was saved from.
+    pub transcript_id: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StyleExample {
+    pub id: i64,
+    #[allow(unused)]
+    pub telegram_id: i64,
+    pub content: String,
+    pub source: String,
     #[allow(unused)]
     pub created_at: OffsetDateTime,
 }
 
+impl fmt::Debug for User {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const REDACTED: &str = "<redacted>";
+
+        f.debug_struct("User")
+            .field("telegram_id", &self.telegram_id)
+            .field("username", &self.username)
+            .field(
+                "typefully_api_key",
+                &self.typefully_api_key.as_ref().map(|_| REDACTED),
+            )
+            .field(
+                "openai_api_key",
+                &self.openai_api_key.as_ref().map(|_| REDACTED),
+            )
+            .field("created_at", &self.created_at)
+            .finish_non_exhaustive()
+    }
+}
+
 impl User {
     pub async fn update_key(&self, db: &Database, api_key: &str) -> Result<()> {
+        let api_key = db.keyring.encrypt(api_key)?;
+
         sqlx::query!(
             r#"UPDATE users SET typefully_api_key = ? WHERE telegram_id = ?"#,
             api_key,
@@ -37,6 +143,8 @@ impl User {
     }
 
     pub async fn update_openai_api_key(&self, db: &Database, openai_api_key: &str) -> Result<()> {
+        let openai_api_key = db.keyring.encrypt(openai_api_key)?;
+
         sqlx::query!(
             r#"
             UPDATE users
@@ -52,13 +160,47 @@ impl User {
         Ok(())
     }
 
-    pub async fn toggle_rewrite(&self, db: &Database) -> Result<bool> {
-        let new_value = !self.rewrite_enabled;
+    pub async fn update_style_profile(&self, db: &Database, name: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET style_profile = ?
+            WHERE telegram_id = ?
+            "#,
+            name,
+            self.telegram_id
+        )
+        .execute(&db.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn toggle_thread(&self, db: &Database) -> Result<bool> {
+        let new_value = !self.thread_enabled;
+
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET thread_enabled = ?
+            WHERE telegram_id = ?
+            "#,
+            new_value,
+            self.telegram_id
+        )
+        .execute(&db.pool)
+        .await?;
+
+        Ok(new_value)
+    }
+
+    pub async fn toggle_share(&self, db: &Database) -> Result<bool> {
+        let new_value = !self.share_enabled;
 
         sqlx::query!(
             r#"
             UPDATE users
-            SET rewrite_enabled = ?
+            SET share_enabled = ?
             WHERE telegram_id = ?
             "#,
             new_value,
@@ -69,18 +211,331 @@ impl User {
 
         Ok(new_value)
     }
+
+    pub async fn toggle_inbox(&self, db: &Database) -> Result<bool> {
+        let new_value = !self.inbox_enabled;
+
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET inbox_enabled = ?
+            WHERE telegram_id = ?
+            "#,
+            new_value,
+            self.telegram_id
+        )
+        .execute(&db.pool)
+        .await?;
+
+        Ok(new_value)
+    }
+
+    pub async fn update_default_schedule(
+        &self,
+        db: &Database,
+        default_schedule: DefaultSchedule,
+    ) -> Result<()> {
+        let default_schedule = default_schedule.to_db_value();
+
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET default_schedule = ?
+            WHERE telegram_id = ?
+            "#,
+            default_schedule,
+            self.telegram_id
+        )
+        .execute(&db.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_utc_offset(&self, db: &Database, offset: UtcOffset) -> Result<()> {
+        let minutes = offset.whole_minutes();
+
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET utc_offset_minutes = ?
+            WHERE telegram_id = ?
+            "#,
+            minutes,
+            self.telegram_id
+        )
+        .execute(&db.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_destination(
+        &self,
+        db: &Database,
+        destination: Destination,
+        webhook_url: Option<&str>,
+    ) -> Result<()> {
+        let destination = destination.to_string();
+
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET destination = ?, webhook_url = COALESCE(?, webhook_url)
+            WHERE telegram_id = ?
+            "#,
+            destination,
+            webhook_url,
+            self.telegram_id
+        )
+        .execute(&db.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_variant_count(&self, db: &Database, variant_count: i64) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET variant_count = ?
+            WHERE telegram_id = ?
+            "#,
+            variant_count,
+            self.telegram_id
+        )
+        .execute(&db.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_ai_base_url(&self, db: &Database, base_url: Option<&str>) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET ai_base_url = ?
+            WHERE telegram_id = ?
+            "#,
+            base_url,
+            self.telegram_id
+        )
+        .execute(&db.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_chat_model(&self, db: &Database, model: Option<&str>) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET chat_model = ?
+            WHERE telegram_id = ?
+            "#,
+            model,
+            self.telegram_id
+        )
+        .execute(&db.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_transcription_model(
+        &self,
+        db: &Database,
+        model: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET transcription_model = ?
+            WHERE telegram_id = ?
+            "#,
+            model,
+            self.telegram_id
+        )
+        .execute(&db.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_transcription_language(
+        &self,
+        db: &Database,
+        language: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET transcription_language = ?
+            WHERE telegram_id = ?
+            "#,
+            language,
+            self.telegram_id
+        )
+        .execute(&db.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_output_language(
+        &self,
+        db: &Database,
+        language: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET output_language = ?
+            WHERE telegram_id = ?
+            "#,
+            language,
+            self.telegram_id
+        )
+        .execute(&db.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub fn destination(&self) -> Destination {
+        self.destination.parse().unwrap_or(Destination::Typefully)
+    }
+
+    pub fn default_schedule(&self) -> DefaultSchedule {
+        DefaultSchedule::from_db_value(self.default_schedule.as_deref())
+    }
+
+    pub fn utc_offset(&self) -> UtcOffset {
+        offset_from_minutes(self.utc_offset_minutes)
+    }
+}
+
+impl PendingDraft {
+    /// The draft's own schedule, falling back to the user's default.
+    pub fn schedule(&self, user: &User) -> Schedule {
+        match &self.schedule {
+            Some(schedule) => Schedule::from_db_value(schedule),
+            None => user
+                .default_schedule()
+                .resolve(OffsetDateTime::now_utc(), user.utc_offset()),
+        }
+    }
 }
 
 pub const FREE_USAGE_LIMIT_SECONDS: i32 = 300;
 
+/// How many variants a user can have generated for a single note.
+pub const MAX_VARIANTS: i64 = 5;
+
+/// How many example posts a user can store.
+pub const MAX_STYLE_EXAMPLES: i64 = 20;
+
+/// Roughly how many characters a person speaks per second, used to meter text input like
+/// voice notes.
+pub const TEXT_CHARS_PER_SECOND: usize = 15;
+
+/// Number of archived transcripts shown per page of /history and /search.
+pub const HISTORY_PAGE_SIZE: i64 = 5;
+
+/// Number of ideas listed by /ideas.
+pub const MAX_LISTED_IDEAS: i64 = 10;
+
 impl Database {
-    pub async fn new(database_url: &str) -> Result<Self> {
+    pub async fn new(database_url: &str, keyring: Keyring) -> Result<Self> {
         let pool = SqlitePool::connect(database_url).await?;
 
         // Run migrations
         sqlx::migrate!("./migrations").run(&pool).await?;
 
-        Ok(Self { pool })
+        let db = Self { pool, keyring };
+
+        let rotated = db.rotate_stored_keys().await?;
+        if rotated > 0 {
+            info!(
+                "Updated {} stored API keys for the current master key",
+                rotated
+            );
+        }
+
+        Ok(db)
+    }
+
+    /// Encrypts plaintext API keys, re-wraps keys encrypted with a retired master key and drops
+    /// keys that can't be decrypted anymore.
+    async fn rotate_stored_keys(&self) -> Result<usize> {
+        let users = sqlx::query!(
+            r#"
+            SELECT telegram_id, typefully_api_key, openai_api_key
+            FROM users
+            WHERE typefully_api_key IS NOT NULL OR openai_api_key IS NOT NULL
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        // A malformed key would make every lookup of its user fail, so it's dropped and the
+        // user has to send it again.
+        let rotate = |telegram_id: i64, value: Option<String>| -> Result<(Option<String>, bool)> {
+            match value {
+                Some(value) if self.keyring.is_malformed(&value) => {
+                    error!(
+                        "Dropping malformed encrypted API key of user {}",
+                        telegram_id
+                    );
+                    Ok((None, true))
+                }
+                Some(value) if self.keyring.needs_rotation(&value) => {
+                    Ok((Some(self.keyring.rotate(&value)?), true))
+                }
+                value => Ok((value, false)),
+            }
+        };
+
+        let mut rotated = 0;
+        for user in users {
+            let (typefully_api_key, typefully_rotated) =
+                rotate(user.telegram_id, user.typefully_api_key)?;
+            let (openai_api_key, openai_rotated) = rotate(user.telegram_id, user.openai_api_key)?;
+
+            if !typefully_rotated && !openai_rotated {
+                continue;
+            }
+
+            sqlx::query!(
+                r#"
+                UPDATE users
+                SET typefully_api_key = ?, openai_api_key = ?
+                WHERE telegram_id = ?
+                "#,
+                typefully_api_key,
+                openai_api_key,
+                user.telegram_id
+            )
+            .execute(&self.pool)
+            .await?;
+
+            rotated += usize::from(typefully_rotated) + usize::from(openai_rotated);
+        }
+
+        Ok(rotated)
+    }
+
+    fn decrypt_user(&self, mut user: User) -> Result<User> {
+        user.typefully_api_key = user
+            .typefully_api_key
+            .map(|key| self.keyring.decrypt(&key))
+            .transpose()?;
+        user.openai_api_key = user
+            .openai_api_key
+            .map(|key| self.keyring.decrypt(&key))
+            .transpose()?;
+
+        Ok(user)
     }
 
     pub async fn get_user(&self, telegram_id: u64) -> Result<Option<User>> {
@@ -88,7 +543,10 @@ impl Database {
         let user = sqlx::query_as!(
             User,
             r#"
-            SELECT telegram_id, username, created_at, typefully_api_key, openai_api_key, rewrite_enabled
+            SELECT telegram_id, username, created_at, typefully_api_key, openai_api_key, style_profile,
+                default_schedule, utc_offset_minutes, thread_enabled, destination, webhook_url, variant_count,
+                ai_base_url, chat_model, transcription_model, transcription_language, output_language,
+                inbox_enabled, share_enabled
             FROM users
             WHERE telegram_id = ?
             "#,
@@ -97,11 +555,14 @@ impl Database {
         .fetch_optional(&self.pool)
         .await?;
 
-        Ok(user)
+        user.map(|user| self.decrypt_user(user)).transpose()
     }
 
     pub async fn create_user(&self, user_payload: UserPayload) -> Result<User> {
         let now = OffsetDateTime::now_utc();
+        // Committing finishes the statement. Reading only the first row of `RETURNING` straight
+        // from the pool would keep the database locked for other connections.
+        let mut tx = self.pool.begin().await?;
 
         let user = sqlx::query_as!(
             User,
@@ -113,9 +574,11 @@ impl Database {
             user_payload.name,
             now,
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await?;
 
+        tx.commit().await?;
+
         Ok(user)
     }
 
@@ -152,6 +615,25 @@ impl Database {
         Ok(())
     }
 
+    pub async fn add_text_usage(&self, telegram_id: i64, chars: usize) -> Result<()> {
+        let now = OffsetDateTime::now_utc();
+        let duration_seconds = chars.div_ceil(TEXT_CHARS_PER_SECOND).max(1) as i32;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO voice_note_usage (telegram_id, duration_seconds, created_at, kind)
+            VALUES (?, ?, ?, 'text')
+            "#,
+            telegram_id,
+            duration_seconds,
+            now,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
     pub async fn has_free_usage(&self, telegram_id: i64) -> Result<bool> {
         let total_usage = self.get_total_usage_seconds(telegram_id).await?;
         Ok(total_usage < FREE_USAGE_LIMIT_SECONDS)
@@ -159,6 +641,8 @@ impl Database {
 
     pub async fn mark_user_deleted(&self, telegram_id: i64, total_usage: i32) -> Result<()> {
         let now = OffsetDateTime::now_utc();
+        // Either the whole account is purged or nothing is
+        let mut tx = self.pool.begin().await?;
 
         sqlx::query!(
             r#"
@@ -169,29 +653,974 @@ impl Database {
             total_usage,
             now,
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
 
-        // Delete user but keep their usage records
         sqlx::query!(
             r#"
-            DELETE FROM users
+            DELETE FROM pending_drafts
             WHERE telegram_id = ?
             "#,
             telegram_id,
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM style_profiles
+            WHERE telegram_id = ?
+            "#,
+            telegram_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM style_examples
+            WHERE telegram_id = ?
+            "#,
+            telegram_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM transcripts
+            WHERE telegram_id = ?
+            "#,
+            telegram_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM ideas
+            WHERE telegram_id = ?
+            "#,
+            telegram_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        // Delete user but keep their usage records
+        sqlx::query!(
+            r#"
+            DELETE FROM users
+            WHERE telegram_id = ?
+            "#,
+            telegram_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// The user's selected style profile, falling back to the default if it no longer exists.
+    pub async fn selected_style_profile(&self, user: &User) -> Result<StyleProfile> {
+        if let Some(style) = StyleProfile::built_in(&user.style_profile) {
+            return Ok(style);
+        }
+
+        Ok(self
+            .get_style_profile(user.telegram_id, &user.style_profile)
+            .await?
+            .unwrap_or_else(StyleProfile::default_profile))
+    }
+
+    pub async fn get_style_profile(
+        &self,
+        telegram_id: i64,
+        name: &str,
+    ) -> Result<Option<StyleProfile>> {
+        let style = sqlx::query!(
+            r#"
+            SELECT name, instructions
+            FROM style_profiles
+            WHERE telegram_id = ? AND name = ?
+            "#,
+            telegram_id,
+            name
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(style.map(|style| StyleProfile::custom(style.name, style.instructions)))
+    }
+
+    pub async fn list_style_profiles(&self, telegram_id: i64) -> Result<Vec<StyleProfile>> {
+        let styles = sqlx::query!(
+            r#"
+            SELECT name, instructions
+            FROM style_profiles
+            WHERE telegram_id = ?
+            ORDER BY name
+            "#,
+            telegram_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(styles
+            .into_iter()
+            .map(|style| StyleProfile::custom(style.name, style.instructions))
+            .collect())
+    }
+
+    /// Creates the style profile or replaces the instructions of an existing one.
+    pub async fn save_style_profile(
+        &self,
+        telegram_id: i64,
+        name: &str,
+        instructions: &str,
+    ) -> Result<()> {
+        let now = OffsetDateTime::now_utc();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO style_profiles (telegram_id, name, instructions, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT (telegram_id, name) DO UPDATE SET instructions = excluded.instructions, updated_at = excluded.updated_at
+            "#,
+            telegram_id,
+            name,
+            instructions,
+            now,
+            now,
+        )
+        .execute(&self.pool)
         .await?;
 
         Ok(())
     }
+
+    /// Returns whether a style profile was removed. Users who had it selected fall back to the
+    /// default style.
+    pub async fn delete_style_profile(&self, telegram_id: i64, name: &str) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM style_profiles
+            WHERE telegram_id = ? AND name = ?
+            "#,
+            telegram_id,
+            name
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET style_profile = ?
+            WHERE telegram_id = ? AND style_profile = ?
+            "#,
+            DEFAULT_STYLE,
+            telegram_id,
+            name
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns whether the example was stored. Examples the user already has are skipped.
+    pub async fn add_style_example(
+        &self,
+        telegram_id: i64,
+        content: &str,
+        source: &str,
+    ) -> Result<bool> {
+        let now = OffsetDateTime::now_utc();
+
+        let result = sqlx::query!(
+            r#"
+            INSERT OR IGNORE INTO style_examples (telegram_id, content, source, created_at)
+            VALUES (?, ?, ?, ?)
+            "#,
+            telegram_id,
+            content,
+            source,
+            now,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn count_style_examples(&self, telegram_id: i64) -> Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count: i64"
+            FROM style_examples
+            WHERE telegram_id = ?
+            "#,
+            telegram_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    pub async fn list_style_examples(&self, telegram_id: i64) -> Result<Vec<StyleExample>> {
+        let examples = sqlx::query_as!(
+            StyleExample,
+            r#"
+            SELECT id AS "id!", telegram_id, content, source, created_at
+            FROM style_examples
+            WHERE telegram_id = ?
+            ORDER BY created_at DESC, id DESC
+            "#,
+            telegram_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(examples)
+    }
+
+    /// A random selection of the user's examples to show the model.
+    pub async fn sample_style_examples(&self, telegram_id: i64, limit: i64) -> Result<Vec<String>> {
+        let examples = sqlx::query_scalar!(
+            r#"
+            SELECT content
+            FROM style_examples
+            WHERE telegram_id = ?
+            ORDER BY RANDOM()
+            LIMIT ?
+            "#,
+            telegram_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(examples)
+    }
+
+    /// Returns whether an example was removed.
+    pub async fn delete_style_example(&self, id: i64, telegram_id: i64) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM style_examples
+            WHERE id = ? AND telegram_id = ?
+            "#,
+            id,
+            telegram_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get_dialogue_state(&self, chat_id: i64) -> Result<Option<String>> {
+        let state = sqlx::query_scalar!(
+            r#"
+            SELECT state
+            FROM dialogues
+            WHERE chat_id = ?
+            "#,
+            chat_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(state)
+    }
+
+    pub async fn set_dialogue_state(&self, chat_id: i64, state: &str) -> Result<()> {
+        let now = OffsetDateTime::now_utc();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO dialogues (chat_id, state, updated_at)
+            VALUES (?, ?, ?)
+            ON CONFLICT (chat_id) DO UPDATE SET state = excluded.state, updated_at = excluded.updated_at
+            "#,
+            chat_id,
+            state,
+            now,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns whether a dialogue was removed.
+    pub async fn remove_dialogue_state(&self, chat_id: i64) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM dialogues
+            WHERE chat_id = ?
+            "#,
+            chat_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Stores a draft written from the archived transcript `transcript_id`.
+    pub async fn create_pending_draft(
+        &self,
+        telegram_id: i64,
+        transcript_id: i64,
+        transcript: &str,
+        language: Option<&str>,
+        content: &str,
+    ) -> Result<PendingDraft> {
+        let now = OffsetDateTime::now_utc();
+        let mut tx = self.pool.begin().await?;
+
+        set_archived_post(&mut *tx, transcript_id, content).await?;
+
+        let draft = sqlx::query_as!(
+            PendingDraft,
+            r#"
+            INSERT INTO pending_drafts (telegram_id, transcript, language, content, created_at,
+                transcript_id)
+            VALUES (?, ?, ?, ?, ?, ?) RETURNING *
+            "#,
+            telegram_id,
+            transcript,
+            language,
+            content,
+            now,
+            transcript_id,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(draft)
+    }
+
+    /// Stores alternative drafts for the same transcript. They share a variant group until the
+    /// user picks one of them.
+    pub async fn create_draft_variants(
+        &self,
+        telegram_id: i64,
+        transcript_id: i64,
+        transcript: &str,
+        language: Option<&str>,
+        contents: &[String],
+    ) -> Result<Vec<PendingDraft>> {
+        let now = OffsetDateTime::now_utc();
+        let mut tx = self.pool.begin().await?;
+
+        // Archived with the first variant until the user sends one of them
+        if let Some(first) = contents.first() {
+            set_archived_post(&mut *tx, transcript_id, first).await?;
+        }
+
+        let mut drafts = Vec::with_capacity(contents.len());
+        for content in contents {
+            let draft = sqlx::query_as!(
+                PendingDraft,
+                r#"
+                INSERT INTO pending_drafts (telegram_id, transcript, language, content, created_at,
+                    transcript_id)
+                VALUES (?, ?, ?, ?, ?, ?) RETURNING *
+                "#,
+                telegram_id,
+                transcript,
+                language,
+                content,
+                now,
+                transcript_id,
+            )
+            .fetch_one(&mut *tx)
+            .await?;
+
+            drafts.push(draft);
+        }
+
+        let group = drafts.first().map(|draft| draft.id);
+        for draft in &mut drafts {
+            sqlx::query!(
+                r#"
+                UPDATE pending_drafts
+                SET variant_group = ?
+                WHERE id = ?
+                "#,
+                group,
+                draft.id
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            draft.variant_group = group;
+        }
+
+        tx.commit().await?;
+
+        Ok(drafts)
+    }
+
+    /// Keeps the picked variant as a regular pending draft and deletes the others. Returns the
+    /// message ids of the deleted variants.
+    pub async fn pick_draft_variant(&self, draft: &PendingDraft) -> Result<Vec<i64>> {
+        let Some(group) = draft.variant_group else {
+            return Ok(Vec::new());
+        };
+
+        let mut tx = self.pool.begin().await?;
+
+        let message_ids = sqlx::query_scalar!(
+            r#"
+            DELETE FROM pending_drafts
+            WHERE variant_group = ? AND id != ?
+            RETURNING message_id
+            "#,
+            group,
+            draft.id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE pending_drafts
+            SET variant_group = NULL
+            WHERE id = ?
+            "#,
+            draft.id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(message_ids.into_iter().flatten().collect())
+    }
+
+    pub async fn get_pending_draft(
+        &self,
+        id: i64,
+        telegram_id: i64,
+    ) -> Result<Option<PendingDraft>> {
+        let draft = sqlx::query_as!(
+            PendingDraft,
+            r#"
+            SELECT id, telegram_id, transcript, content, created_at, message_id, schedule, variant_group,
+                language, transcript_id
+            FROM pending_drafts
+            WHERE id = ? AND telegram_id = ?
+            "#,
+            id,
+            telegram_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(draft)
+    }
+
+    pub async fn get_pending_draft_by_message(
+        &self,
+        telegram_id: i64,
+        message_id: i32,
+    ) -> Result<Option<PendingDraft>> {
+        let draft = sqlx::query_as!(
+            PendingDraft,
+            r#"
+            SELECT id, telegram_id, transcript, content, created_at, message_id, schedule, variant_group,
+                language, transcript_id
+            FROM pending_drafts
+            WHERE telegram_id = ? AND message_id = ?
+            "#,
+            telegram_id,
+            message_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(draft)
+    }
+
+    pub async fn set_pending_draft_message(&self, id: i64, message_id: i32) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE pending_drafts
+            SET message_id = ?
+            WHERE id = ?
+            "#,
+            message_id,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_pending_draft_content(&self, id: i64, content: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE pending_drafts
+            SET content = ?
+            WHERE id = ?
+            "#,
+            content,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_latest_pending_draft(&self, telegram_id: i64) -> Result<Option<PendingDraft>> {
+        let draft = sqlx::query_as!(
+            PendingDraft,
+            r#"
+            SELECT id, telegram_id, transcript, content, created_at, message_id, schedule, variant_group,
+                language, transcript_id
+            FROM pending_drafts
+            WHERE telegram_id = ? AND variant_group IS NULL
+            ORDER BY id DESC
+            LIMIT 1
+            "#,
+            telegram_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(draft)
+    }
+
+    pub async fn set_pending_draft_schedule(&self, id: i64, schedule: Schedule) -> Result<()> {
+        let schedule = schedule.to_db_value();
+
+        sqlx::query!(
+            r#"
+            UPDATE pending_drafts
+            SET schedule = ?
+            WHERE id = ?
+            "#,
+            schedule,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Keeps a transcript as soon as it exists, so it can be searched even if no post is written
+    /// from it.
+    pub async fn archive_transcript(&self, telegram_id: i64, transcript: &str) -> Result<i64> {
+        insert_transcript(&self.pool, telegram_id, transcript).await
+    }
+
+    /// Replaces the archived post with the one the user actually sent and remembers where it went.
+    pub async fn update_archived_post(
+        &self,
+        id: i64,
+        post: &str,
+        published: &Published,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE transcripts
+            SET post = ?, typefully_draft_id = ?, typefully_url = ?, typefully_share_url = ?
+            WHERE id = ?
+            "#,
+            post,
+            published.draft_id,
+            published.edit_url,
+            published.share_url,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_archived_transcript(
+        &self,
+        id: i64,
+        telegram_id: i64,
+    ) -> Result<Option<ArchivedTranscript>> {
+        let transcript = sqlx::query_as!(
+            ArchivedTranscript,
+            r#"
+            SELECT id, telegram_id, transcript, post, created_at, typefully_url, typefully_share_url
+            FROM transcripts
+            WHERE id = ? AND telegram_id = ?
+            "#,
+            id,
+            telegram_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(transcript)
+    }
+
+    pub async fn count_archived_transcripts(&self, telegram_id: i64) -> Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count: i64"
+            FROM transcripts
+            WHERE telegram_id = ?
+            "#,
+            telegram_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// One page of the user's archived transcripts, newest first.
+    pub async fn list_archived_transcripts(
+        &self,
+        telegram_id: i64,
+        page: i64,
+    ) -> Result<Vec<ArchivedTranscript>> {
+        let offset = page * HISTORY_PAGE_SIZE;
+
+        let transcripts = sqlx::query_as!(
+            ArchivedTranscript,
+            r#"
+            SELECT id AS "id!", telegram_id, transcript, post, created_at, typefully_url, typefully_share_url
+            FROM transcripts
+            WHERE telegram_id = ?
+            ORDER BY created_at DESC, id DESC
+            LIMIT ? OFFSET ?
+            "#,
+            telegram_id,
+            HISTORY_PAGE_SIZE,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(transcripts)
+    }
+
+    /// The user's archived transcripts containing all words of `query`, best matches first.
+    pub async fn search_archived_transcripts(
+        &self,
+        telegram_id: i64,
+        query: &str,
+    ) -> Result<Vec<ArchivedTranscript>> {
+        let query = fts_query(query);
+
+        let transcripts = sqlx::query_as!(
+            ArchivedTranscript,
+            r#"
+            SELECT t.id AS "id!", t.telegram_id, t.transcript, t.post, t.created_at, t.typefully_url,
+                t.typefully_share_url
+            FROM transcripts_fts
+            JOIN transcripts t ON t.id = transcripts_fts.rowid
+            WHERE transcripts_fts MATCH ? AND t.telegram_id = ?
+            ORDER BY transcripts_fts.rank
+            LIMIT ?
+            "#,
+            query,
+            telegram_id,
+            HISTORY_PAGE_SIZE
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(transcripts)
+    }
+
+    pub async fn create_idea(
+        &self,
+        telegram_id: i64,
+        transcript: &str,
+        language: Option<&str>,
+        tags: &str,
+    ) -> Result<Idea> {
+        let now = OffsetDateTime::now_utc();
+        let mut tx = self.pool.begin().await?;
+
+        let transcript_id = insert_transcript(&mut *tx, telegram_id, transcript).await?;
+
+        let idea = sqlx::query_as!(
+            Idea,
+            r#"
+            INSERT INTO ideas (telegram_id, transcript, language, tags, created_at, transcript_id)
+            VALUES (?, ?, ?, ?, ?, ?) RETURNING *
+            "#,
+            telegram_id,
+            transcript,
+            language,
+            tags,
+            now,
+            transcript_id,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(idea)
+    }
+
+    pub async fn get_idea(&self, id: i64, telegram_id: i64) -> Result<Option<Idea>> {
+        let idea = sqlx::query_as!(
+            Idea,
+            r#"
+            SELECT id, telegram_id, transcript, language, tags, created_at, transcript_id
+            FROM ideas
+            WHERE id = ? AND telegram_id = ?
+            "#,
+            id,
+            telegram_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(idea)
+    }
+
+    pub async fn count_ideas(&self, telegram_id: i64) -> Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count: i64"
+            FROM ideas
+            WHERE telegram_id = ?
+            "#,
+            telegram_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// The user's most recent ideas, newest first.
+    pub async fn list_ideas(&self, telegram_id: i64) -> Result<Vec<Idea>> {
+        let ideas = sqlx::query_as!(
+            Idea,
+            r#"
+            SELECT id AS "id!", telegram_id, transcript, language, tags, created_at, transcript_id
+            FROM ideas
+            WHERE telegram_id = ?
+            ORDER BY created_at DESC, id DESC
+            LIMIT ?
+            "#,
+            telegram_id,
+            MAX_LISTED_IDEAS
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(ideas)
+    }
+
+    pub async fn delete_idea(&self, id: i64) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM ideas
+            WHERE id = ?
+            "#,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_pending_draft(&self, id: i64) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM pending_drafts
+            WHERE id = ?
+            "#,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+async fn insert_transcript<'e, E>(executor: E, telegram_id: i64, transcript: &str) -> Result<i64>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let now = OffsetDateTime::now_utc();
+
+    // Not `RETURNING`, see `create_user`
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO transcripts (telegram_id, transcript, created_at)
+        VALUES (?, ?, ?)
+        "#,
+        telegram_id,
+        transcript,
+        now,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+async fn set_archived_post<'e, E>(executor: E, id: i64, post: &str) -> Result<()>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    sqlx::query!(
+        r#"
+        UPDATE transcripts
+        SET post = ?
+        WHERE id = ?
+        "#,
+        post,
+        id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Quotes every word so user input can't be read as FTS5 query syntax.
+fn fts_query(input: &str) -> String {
+    input
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
 impl From<Chat> for UserPayload {
     fn from(chat: Chat) -> Self {
         Self {
-            telegram_id: chat.id.0 as i64,
+            telegram_id: chat.id.0,
             name: chat.first_name().unwrap_or_default().to_string(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TELEGRAM_ID: i64 = 1;
+
+    async fn database() -> Database {
+        let path = std::env::temp_dir().join(format!("bot-test-{}.db", uuid::Uuid::new_v4()));
+        let keyring = Keyring::new(&format!("{}=", "A".repeat(43)), &[]).unwrap();
+
+        let db = Database::new(&format!("sqlite://{}?mode=rwc", path.display()), keyring)
+            .await
+            .unwrap();
+        db.create_user(UserPayload {
+            telegram_id: TELEGRAM_ID,
+            name: "alice".to_string(),
+        })
+        .await
+        .unwrap();
+
+        db
+    }
+
+    async fn indexed_transcripts(db: &Database, word: &str) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM transcripts_fts WHERE transcripts_fts MATCH ?")
+            .bind(word)
+            .fetch_one(&db.pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn finds_transcripts_without_posts() {
+        let db = database().await;
+
+        let idea = db
+            .create_idea(TELEGRAM_ID, "Pricing for the new plan", None, "#pricing")
+            .await
+            .unwrap();
+        // Archived, but writing the post failed
+        db.archive_transcript(TELEGRAM_ID, "Hiring plans for spring")
+            .await
+            .unwrap();
+
+        let found = db
+            .search_archived_transcripts(TELEGRAM_ID, "pricing")
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(Some(found[0].id), idea.transcript_id);
+        assert_eq!(found[0].post, None);
+
+        assert_eq!(
+            db.search_archived_transcripts(TELEGRAM_ID, "hiring")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn drafts_attach_their_post() {
+        let db = database().await;
+
+        let transcript_id = db
+            .archive_transcript(TELEGRAM_ID, "Notes about pricing")
+            .await
+            .unwrap();
+        db.create_pending_draft(
+            TELEGRAM_ID,
+            transcript_id,
+            "Notes about pricing",
+            None,
+            "Our pricing explained",
+        )
+        .await
+        .unwrap();
+
+        let transcript = db
+            .get_archived_transcript(transcript_id, TELEGRAM_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(transcript.post.as_deref(), Some("Our pricing explained"));
+        assert_eq!(db.count_archived_transcripts(TELEGRAM_ID).await.unwrap(), 1);
+        assert_eq!(indexed_transcripts(&db, "explained").await, 1);
+    }
+
+    #[tokio::test]
+    async fn deleting_an_account_purges_transcripts() {
+        let db = database().await;
+
+        db.create_idea(TELEGRAM_ID, "Pricing for the new plan", None, "")
+            .await
+            .unwrap();
+        db.archive_transcript(TELEGRAM_ID, "Pricing once more")
+            .await
+            .unwrap();
+        assert_eq!(indexed_transcripts(&db, "pricing").await, 2);
+
+        db.mark_user_deleted(TELEGRAM_ID, 0).await.unwrap();
+
+        assert_eq!(db.count_archived_transcripts(TELEGRAM_ID).await.unwrap(), 0);
+        assert_eq!(indexed_transcripts(&db, "pricing").await, 0);
+        assert!(db.list_ideas(TELEGRAM_ID).await.unwrap().is_empty());
+        assert!(db.get_user(TELEGRAM_ID as u64).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,43 @@
+/// Adding this tag to a voice note's caption or a text note saves it as an idea.
+pub const IDEA_TAG: &str = "#idea";
+
+/// Whether the caption or text asks for the note to be saved as an idea.
+pub fn is_idea(text: &str) -> bool {
+    hashtags(text).any(|tag| tag.eq_ignore_ascii_case(IDEA_TAG))
+}
+
+/// The hashtags in `text` other than the idea tag, lowercased and separated by spaces.
+pub fn tags(text: &str) -> String {
+    let mut tags = hashtags(text)
+        .filter(|tag| !tag.eq_ignore_ascii_case(IDEA_TAG))
+        .map(|tag| tag.to_lowercase())
+        .collect::<Vec<_>>();
+    tags.dedup();
+
+    tags.join(" ")
+}
+
+/// `text` without the idea tag, keeping its line breaks.
+pub fn strip_idea_tag(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            line.split_whitespace()
+                .filter(|word| !trim_punctuation(word).eq_ignore_ascii_case(IDEA_TAG))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+fn hashtags(text: &str) -> impl Iterator<Item = &str> {
+    text.split_whitespace()
+        .map(trim_punctuation)
+        .filter(|word| word.len() > 1 && word.starts_with('#'))
+}
+
+fn trim_punctuation(word: &str) -> &str {
+    word.trim_end_matches(|c: char| c.is_ascii_punctuation())
+}
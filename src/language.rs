@@ -0,0 +1,53 @@
+use anyhow::{bail, Result};
+
+/// ISO 639-1 codes and English names of the languages Whisper transcribes well.
+const LANGUAGES: &[(&str, &str)] = &[
+    ("ar", "Arabic"),
+    ("cs", "Czech"),
+    ("da", "Danish"),
+    ("de", "German"),
+    ("el", "Greek"),
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("fi", "Finnish"),
+    ("fr", "French"),
+    ("hi", "Hindi"),
+    ("hu", "Hungarian"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("nl", "Dutch"),
+    ("no", "Norwegian"),
+    ("pl", "Polish"),
+    ("pt", "Portuguese"),
+    ("ro", "Romanian"),
+    ("ru", "Russian"),
+    ("sv", "Swedish"),
+    ("tr", "Turkish"),
+    ("uk", "Ukrainian"),
+    ("zh", "Chinese"),
+];
+
+fn find(input: &str) -> Option<&'static (&'static str, &'static str)> {
+    let input = input.trim();
+
+    LANGUAGES
+        .iter()
+        .find(|(code, name)| code.eq_ignore_ascii_case(input) || name.eq_ignore_ascii_case(input))
+}
+
+/// Parses a language code like `de` or an English name like `German` into its code.
+pub fn parse_language(input: &str) -> Result<&'static str> {
+    match find(input) {
+        Some((code, _)) => Ok(code),
+        None => bail!("Unknown language '{}'", input.trim()),
+    }
+}
+
+/// The English name of a language code or name, or the input itself if it isn't known.
+pub fn language_name(language: &str) -> String {
+    match find(language) {
+        Some((_, name)) => name.to_string(),
+        None => language.to_string(),
+    }
+}
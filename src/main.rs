@@ -1,18 +1,32 @@
-use commands::{bot_schema, BotCommand, State};
+use commands::{bot_schema, BotCommand};
 use log::error;
 use std::{path::PathBuf, sync::Arc};
 
+use crypto::Keyring;
 use db::Database;
+use providers::ProviderConfig;
 
-use teloxide::{
-    dispatching::dialogue::InMemStorage, prelude::*, types::BotCommand as TeloxideBotCommand,
-    utils::command::BotCommands,
-};
+use teloxide::{prelude::*, types::BotCommand as TeloxideBotCommand, utils::command::BotCommands};
 
 mod actions;
 mod ai;
 mod commands;
+mod crypto;
 mod db;
+mod ideas;
+mod language;
+mod media;
+mod progress;
+mod providers;
+mod publishers;
+mod schedule;
+mod storage;
+mod styles;
+mod thread;
+mod typefully;
+mod urls;
+#[cfg(feature = "local-whisper")]
+mod whisper;
 
 const DOWNLOAD_DIR: &str = "./voice-notes";
 const DATABASE_URL: &str = "sqlite:bot.db";
@@ -25,6 +39,7 @@ async fn main() -> anyhow::Result<()> {
     log::info!("Starting Typefully drafting bot...");
 
     let bot = Bot::from_env();
+    let providers = Arc::new(ProviderConfig::from_env()?);
 
     // Set bot commands for autocompletion
     let commands = BotCommand::bot_commands();
@@ -34,7 +49,8 @@ async fn main() -> anyhow::Result<()> {
         .collect();
     bot.set_my_commands(commands).await?;
 
-    let db = Arc::new(Database::new(DATABASE_URL).await?);
+    let keyring = Keyring::from_env()?;
+    let db = Arc::new(Database::new(DATABASE_URL, keyring).await?);
 
     if !PathBuf::new().join(DOWNLOAD_DIR).exists() {
         tokio::fs::create_dir(DOWNLOAD_DIR).await.map_err(|e| {
@@ -44,7 +60,7 @@ async fn main() -> anyhow::Result<()> {
     }
 
     Dispatcher::builder(bot, bot_schema())
-        .dependencies(dptree::deps![InMemStorage::<State>::new(), db])
+        .dependencies(dptree::deps![db, providers])
         .enable_ctrlc_handler()
         .build()
         .dispatch()
@@ -0,0 +1,316 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+use teloxide::types::Message;
+use tokio::process::Command;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaKind {
+    Voice,
+    Audio,
+    VideoNote,
+    Video,
+}
+
+impl MediaKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Voice => "voice note",
+            Self::Audio => "audio file",
+            Self::VideoNote => "video note",
+            Self::Video => "video",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Voice => "ogg",
+            Self::Audio => "audio",
+            Self::VideoNote | Self::Video => "mp4",
+        }
+    }
+
+    /// Voice notes are Opus in Ogg which Whisper accepts directly, everything else is
+    /// converted to a mono MP3 first.
+    pub fn needs_extraction(&self) -> bool {
+        !matches!(self, Self::Voice)
+    }
+}
+
+/// Any message attachment we can get a transcript from.
+#[derive(Debug, Clone)]
+pub struct Media {
+    pub kind: MediaKind,
+    pub file_id: String,
+    pub duration_seconds: i32,
+    pub size_bytes: u32,
+}
+
+impl Media {
+    pub fn from_message(msg: Message) -> Option<Self> {
+        let (kind, file, duration) = if let Some(voice) = msg.voice() {
+            (MediaKind::Voice, &voice.file, voice.duration)
+        } else if let Some(audio) = msg.audio() {
+            (MediaKind::Audio, &audio.file, audio.duration)
+        } else if let Some(video_note) = msg.video_note() {
+            (MediaKind::VideoNote, &video_note.file, video_note.duration)
+        } else if let Some(video) = msg.video() {
+            (MediaKind::Video, &video.file, video.duration)
+        } else {
+            return None;
+        };
+
+        Some(Self {
+            kind,
+            file_id: file.id.clone(),
+            duration_seconds: duration.seconds() as i32,
+            size_bytes: file.size,
+        })
+    }
+
+    /// Fails for files Telegram won't let the bot download, before anything is fetched.
+    pub fn check_downloadable(&self) -> Result<(), TooLargeToDownload> {
+        if self.size_bytes > MAX_DOWNLOAD_BYTES {
+            return Err(TooLargeToDownload {
+                kind: self.kind.label(),
+                size_mb: self.size_bytes.div_ceil(1024 * 1024),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// The Bot API only serves files up to 20 MB to bots.
+pub const MAX_DOWNLOAD_BYTES: u32 = 20 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+#[error("This {kind} is {size_mb} MB, but Telegram only lets bots download files up to 20 MB. Please send a shorter or more compressed recording.")]
+pub struct TooLargeToDownload {
+    kind: &'static str,
+    size_mb: u32,
+}
+
+/// Whisper rejects uploads above 25 MB, keep some headroom for the multipart overhead. Voice
+/// notes are uploaded as downloaded and stay below it, but audio extracted from files and videos
+/// can exceed it, e.g. a 20 MB audio file at 32 kbit/s is an 80 minute, 40 MB MP3.
+pub const MAX_UPLOAD_BYTES: u64 = 24 * 1024 * 1024;
+
+/// 10 minutes of 64 kbit/s MP3 are roughly 5 MB, well below the upload limit.
+const SEGMENT_SECONDS: i32 = 600;
+/// Segments overlap so words cut at a boundary are fully contained in one of them.
+const SEGMENT_OVERLAP_SECONDS: i32 = 5;
+
+/// Extracts the audio track of `input` into a mono MP3 at `output` using ffmpeg.
+pub async fn extract_audio(input: &Path, output: &Path) -> anyhow::Result<()> {
+    run_ffmpeg(
+        ffmpeg()
+            .arg("-i")
+            .arg(input)
+            .args(["-vn", "-ac", "1", "-c:a", "libmp3lame", "-b:a", "64k"])
+            .arg(output),
+        "extract audio",
+    )
+    .await
+}
+
+/// Converts `input` into the 16 kHz mono WAV whisper.cpp expects.
+#[cfg(feature = "local-whisper")]
+pub async fn convert_to_wav(input: &Path, output: &Path) -> anyhow::Result<()> {
+    run_ffmpeg(
+        ffmpeg()
+            .arg("-i")
+            .arg(input)
+            .args(["-vn", "-ac", "1", "-ar", "16000", "-c:a", "pcm_s16le"])
+            .arg(output),
+        "convert audio",
+    )
+    .await
+}
+
+/// An ffmpeg command that overwrites its output.
+fn ffmpeg() -> Command {
+    let mut command = Command::new("ffmpeg");
+    command.arg("-y");
+    command
+}
+
+/// Runs ffmpeg, failing with the last line of its output, which names the problem.
+async fn run_ffmpeg(command: &mut Command, action: &str) -> anyhow::Result<()> {
+    let result = command
+        .output()
+        .await
+        .context("Failed to run ffmpeg, is it installed?")?;
+
+    if !result.status.success() {
+        bail!(
+            "ffmpeg failed to {}: {}",
+            action,
+            String::from_utf8_lossy(&result.stderr)
+                .lines()
+                .last()
+                .unwrap_or_default()
+        );
+    }
+
+    Ok(())
+}
+
+/// Splits `input` into overlapping MP3 segments if it is too large to upload in one request.
+/// Returns `input` itself when no split is needed.
+pub async fn split_for_upload(input: &Path, duration_seconds: i32) -> anyhow::Result<Vec<PathBuf>> {
+    let size = tokio::fs::metadata(input).await?.len();
+    if size <= MAX_UPLOAD_BYTES {
+        return Ok(vec![input.to_path_buf()]);
+    }
+
+    let duration_seconds = if duration_seconds > 0 {
+        duration_seconds
+    } else {
+        probe_duration(input).await?
+    };
+
+    let stem = input
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("audio");
+
+    let mut segments = Vec::new();
+
+    for start in segment_starts(duration_seconds) {
+        let segment = input.with_file_name(format!("{}-part{}.mp3", stem, segments.len() + 1));
+
+        let result = run_ffmpeg(
+            ffmpeg()
+                .args([
+                    "-ss",
+                    &start.to_string(),
+                    "-t",
+                    &SEGMENT_SECONDS.to_string(),
+                ])
+                .arg("-i")
+                .arg(input)
+                .args(["-vn", "-ac", "1", "-c:a", "libmp3lame", "-b:a", "64k"])
+                .arg(&segment),
+            "split audio",
+        )
+        .await;
+
+        if let Err(e) = result {
+            for segment in &segments {
+                let _ = tokio::fs::remove_file(segment).await;
+            }
+            return Err(e);
+        }
+
+        segments.push(segment);
+    }
+
+    Ok(segments)
+}
+
+/// Where each segment of a recording starts, so that consecutive segments overlap and the last
+/// one reaches the end.
+fn segment_starts(duration_seconds: i32) -> Vec<i32> {
+    let step = SEGMENT_SECONDS - SEGMENT_OVERLAP_SECONDS;
+
+    let mut starts = vec![0];
+    while starts[starts.len() - 1] + SEGMENT_SECONDS < duration_seconds {
+        starts.push(starts[starts.len() - 1] + step);
+    }
+
+    starts
+}
+
+async fn probe_duration(input: &Path) -> anyhow::Result<i32> {
+    let result = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(input)
+        .output()
+        .await
+        .context("Failed to run ffprobe, is it installed?")?;
+
+    let duration: f64 = String::from_utf8_lossy(&result.stdout)
+        .trim()
+        .parse()
+        .context("ffprobe returned no duration")?;
+
+    Ok(duration.ceil() as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ai::stitch_transcripts;
+
+    use super::*;
+
+    #[test]
+    fn segments_cover_the_recording() {
+        assert_eq!(segment_starts(30), vec![0]);
+        assert_eq!(segment_starts(SEGMENT_SECONDS), vec![0]);
+        assert_eq!(segment_starts(SEGMENT_SECONDS + 1), vec![0, 595]);
+
+        for duration in [601, 1800, 3601, 5 * 3600] {
+            let starts = segment_starts(duration);
+
+            assert!(starts[starts.len() - 1] + SEGMENT_SECONDS >= duration);
+            for pair in starts.windows(2) {
+                assert_eq!(pair[0] + SEGMENT_SECONDS - pair[1], SEGMENT_OVERLAP_SECONDS);
+            }
+        }
+    }
+
+    #[test]
+    fn stitches_a_long_recording_back_together() {
+        // Two words per second for 90 minutes, every segment transcribes the words spoken in it
+        let duration = 90 * 60;
+        let words: Vec<String> = (0..duration * 2).map(|i| format!("word{}", i)).collect();
+
+        let parts: Vec<String> = segment_starts(duration)
+            .into_iter()
+            .map(|start| {
+                let end = ((start + SEGMENT_SECONDS) * 2).min(duration * 2);
+                words[(start * 2) as usize..end as usize].join(" ")
+            })
+            .collect();
+
+        assert_eq!(parts.len(), 10);
+        assert_eq!(stitch_transcripts(parts), words.join(" "));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs ffmpeg"]
+    async fn splits_recordings_above_the_upload_limit() {
+        let dir = std::env::temp_dir().join(format!("split-{}", uuid::Uuid::new_v4()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let input = dir.join("long.mp3");
+
+        // 11 minutes at 320 kbit/s are about 26 MB
+        run_ffmpeg(
+            ffmpeg()
+                .args(["-f", "lavfi", "-i", "sine=frequency=440:duration=660"])
+                .args(["-c:a", "libmp3lame", "-b:a", "320k"])
+                .arg(&input),
+            "generate audio",
+        )
+        .await
+        .unwrap();
+        assert!(tokio::fs::metadata(&input).await.unwrap().len() > MAX_UPLOAD_BYTES);
+
+        let segments = split_for_upload(&input, 660).await.unwrap();
+
+        assert_eq!(segments.len(), 2);
+        for segment in &segments {
+            assert!(tokio::fs::metadata(segment).await.unwrap().len() <= MAX_UPLOAD_BYTES);
+        }
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,235 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use log::info;
+use teloxide::{
+    prelude::*,
+    types::{ChatAction, InlineKeyboardMarkup, MessageId},
+};
+use tokio::task::JoinHandle;
+
+/// How often the chat action is repeated. Telegram shows it for about five seconds.
+const ACTION_INTERVAL: Duration = Duration::from_secs(4);
+
+/// The status message is refreshed with the elapsed time every this many chat actions.
+const REFRESH_EVERY: u32 = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stage {
+    Downloading,
+    Transcribing,
+    Writing,
+    Revising,
+}
+
+impl Stage {
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Downloading => "⬇️ Downloading",
+            Self::Transcribing => "🎙 Transcribing",
+            Self::Writing => "✍️ Writing your post",
+            Self::Revising => "✍️ Revising your post",
+        }
+    }
+
+    fn failure(&self) -> &'static str {
+        match self {
+            Self::Downloading => "Downloading failed",
+            Self::Transcribing => "Transcription failed",
+            Self::Writing => "Writing the post failed",
+            Self::Revising => "Revising the post failed",
+        }
+    }
+
+    fn action(&self) -> ChatAction {
+        match self {
+            Self::Downloading | Self::Transcribing => ChatAction::RecordVoice,
+            Self::Writing | Self::Revising => ChatAction::Typing,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+struct Status {
+    stage: Stage,
+    detail: Option<String>,
+}
+
+/// A single status message that is edited in place as a note moves through the stages, while a
+/// chat action shows that the bot is busy.
+pub struct Progress {
+    bot: Bot,
+    chat_id: ChatId,
+    message_id: MessageId,
+    started: Instant,
+    status: Arc<Mutex<Status>>,
+    ticker: JoinHandle<()>,
+}
+
+impl Progress {
+    pub async fn start(bot: &Bot, chat_id: ChatId, stage: Stage) -> anyhow::Result<Self> {
+        let started = Instant::now();
+        let status = Status {
+            stage,
+            detail: None,
+        };
+
+        let message = bot
+            .send_message(chat_id, status_text(&status, started))
+            .await?;
+
+        let status = Arc::new(Mutex::new(status));
+        let ticker = tokio::spawn(tick(
+            bot.clone(),
+            chat_id,
+            message.id,
+            started,
+            status.clone(),
+        ));
+
+        Ok(Self {
+            bot: bot.clone(),
+            chat_id,
+            message_id: message.id,
+            started,
+            status,
+            ticker,
+        })
+    }
+
+    pub fn chat_id(&self) -> ChatId {
+        self.chat_id
+    }
+
+    pub fn message_id(&self) -> MessageId {
+        self.message_id
+    }
+
+    pub async fn update(&self, stage: Stage) {
+        self.set(Status {
+            stage,
+            detail: None,
+        })
+        .await;
+    }
+
+    /// Like [`Progress::update`], with a note like "part 2/3" after the stage.
+    pub async fn update_with(&self, stage: Stage, detail: impl Into<String>) {
+        self.set(Status {
+            stage,
+            detail: Some(detail.into()),
+        })
+        .await;
+    }
+
+    async fn set(&self, status: Status) {
+        {
+            let mut current = self.status.lock().unwrap();
+            if *current == status {
+                return;
+            }
+            *current = status.clone();
+        }
+
+        let text = status_text(&status, self.started);
+
+        if let Err(e) = self
+            .bot
+            .edit_message_text(self.chat_id, self.message_id, text)
+            .await
+        {
+            info!("Failed to update progress message: {}", e);
+        }
+    }
+
+    /// The time since the note arrived, e.g. `12s` or `1m 05s`.
+    pub fn elapsed(&self) -> String {
+        describe_elapsed(self.started.elapsed())
+    }
+
+    /// Replaces the status with the final result.
+    pub async fn finish(
+        self,
+        text: String,
+        keyboard: Option<InlineKeyboardMarkup>,
+    ) -> anyhow::Result<()> {
+        self.ticker.abort();
+
+        let edit = self
+            .bot
+            .edit_message_text(self.chat_id, self.message_id, text);
+        match keyboard {
+            Some(keyboard) => edit.reply_markup(keyboard).await?,
+            None => edit.await?,
+        };
+
+        Ok(())
+    }
+
+    /// Shows which stage failed and why.
+    pub async fn fail(self, message: &str) -> anyhow::Result<()> {
+        let stage = self.status.lock().unwrap().stage;
+        let text = format!(
+            "❌ {} after {}.\n\n{}",
+            stage.failure(),
+            self.elapsed(),
+            message
+        );
+
+        self.finish(text, None).await
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.ticker.abort();
+    }
+}
+
+/// Keeps the chat action alive and the elapsed time current until the progress is finished.
+async fn tick(
+    bot: Bot,
+    chat_id: ChatId,
+    message_id: MessageId,
+    started: Instant,
+    status: Arc<Mutex<Status>>,
+) {
+    let mut ticks = 0u32;
+
+    loop {
+        let current = status.lock().unwrap().clone();
+
+        if ticks == REFRESH_EVERY {
+            ticks = 0;
+            let _ = bot
+                .edit_message_text(chat_id, message_id, status_text(&current, started))
+                .await;
+        }
+
+        let _ = bot.send_chat_action(chat_id, current.stage.action()).await;
+
+        ticks += 1;
+        tokio::time::sleep(ACTION_INTERVAL).await;
+    }
+}
+
+fn status_text(status: &Status, started: Instant) -> String {
+    let elapsed = describe_elapsed(started.elapsed());
+
+    match &status.detail {
+        Some(detail) => format!("{} ({})… {}", status.stage.label(), detail, elapsed),
+        None => format!("{}… {}", status.stage.label(), elapsed),
+    }
+}
+
+fn describe_elapsed(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs();
+
+    if seconds < 60 {
+        format!("{}s", seconds)
+    } else {
+        format!("{}m {:02}s", seconds / 60, seconds % 60)
+    }
+}
@@ -0,0 +1,620 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use openai_api_rs::v1::{
+    api::OpenAIClient,
+    audio::WHISPER_1,
+    chat_completion::{self, ChatCompletionMessage, ChatCompletionRequest, MessageRole},
+    common::GPT4_O_MINI,
+    error::APIError,
+};
+use reqwest::multipart::{Form, Part};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[cfg(feature = "local-whisper")]
+use crate::whisper::LocalWhisper;
+use crate::{
+    db::User,
+    urls::{self, UrlPolicy, UrlRejected},
+};
+
+/// A prompt sent to a [`Writer`].
+pub struct CompletionRequest<'a> {
+    pub from_user: &'a str,
+    pub instructions: &'a str,
+    pub text: &'a str,
+    /// How many alternative completions to return.
+    pub variants: i64,
+    /// Ask for a JSON object instead of free text.
+    pub json: bool,
+}
+
+const DEFAULT_OPENAI_API_URL: &str = "https://api.openai.com/v1";
+
+/// How long a request to a server the user chose may take, long enough to transcribe a
+/// segment of a long recording.
+const USER_SERVER_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Uses `OPENAI_API_BASE` like `openai-api-rs` does, defaulting to the public API.
+pub fn openai_base_url() -> String {
+    std::env::var("OPENAI_API_BASE").unwrap_or_else(|_| DEFAULT_OPENAI_API_URL.to_string())
+}
+
+pub struct Transcript {
+    pub text: String,
+    /// The spoken language, if the transcriber detected it or was told.
+    pub language: Option<String>,
+}
+
+#[async_trait]
+pub trait Transcriber: Send + Sync {
+    /// Transcribes the audio file, assuming `language` was spoken if given.
+    async fn transcribe(&self, path: &Path, language: Option<&str>) -> anyhow::Result<Transcript>;
+}
+
+#[async_trait]
+pub trait Writer: Send + Sync {
+    /// Returns one completion per requested variant.
+    async fn complete(&self, request: &CompletionRequest<'_>) -> anyhow::Result<Vec<String>>;
+}
+
+/// The API refused a model because it doesn't exist or the key has no access to it.
+#[derive(Debug, thiserror::Error)]
+#[error("The model '{model}' was rejected: {reason} Please choose another one with /model.")]
+pub struct ModelRejected {
+    pub model: String,
+    pub reason: String,
+}
+
+/// The transcriber and writer used for a single user's request.
+pub struct Provider {
+    pub transcriber: Box<dyn Transcriber>,
+    pub writer: Box<dyn Writer>,
+}
+
+/// A server requests are sent to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    pub base_url: String,
+    /// Chosen by the user rather than the operator, so it must be a public server.
+    pub user_supplied: bool,
+}
+
+impl Endpoint {
+    fn openai() -> Self {
+        Self {
+            base_url: openai_base_url(),
+            user_supplied: false,
+        }
+    }
+
+    pub fn client(&self) -> reqwest::Client {
+        if self.user_supplied {
+            urls::public_client(USER_SERVER_TIMEOUT)
+        } else {
+            reqwest::Client::new()
+        }
+    }
+
+    /// Makes sure a server the user chose is still allowed before anything is sent to it.
+    pub async fn check(&self) -> Result<(), UrlRejected> {
+        if self.user_supplied {
+            UrlPolicy::ai_base_urls().check(&self.base_url).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Operator configuration deciding which provider handles requests.
+#[derive(Debug, Clone)]
+pub struct ProviderConfig {
+    /// An OpenAI-compatible server used instead of OpenAI, e.g. a self-hosted model or a mock.
+    base_url: Option<String>,
+    chat_model: String,
+    transcription_model: String,
+    /// Models users on the free tier may choose, always including the defaults.
+    free_chat_models: Vec<String>,
+    free_transcription_models: Vec<String>,
+    /// Transcribes every note locally instead of sending it to the provider when configured.
+    #[cfg(feature = "local-whisper")]
+    local_whisper: Option<LocalWhisper>,
+}
+
+impl ProviderConfig {
+    /// Reads `AI_BASE_URL`, `AI_CHAT_MODEL`, `AI_TRANSCRIPTION_MODEL`, `FREE_CHAT_MODELS` and
+    /// `FREE_TRANSCRIPTION_MODELS`, and the whisper.cpp setup with the `local-whisper` feature.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let chat_model = std::env::var("AI_CHAT_MODEL").unwrap_or(GPT4_O_MINI.to_string());
+        let transcription_model =
+            std::env::var("AI_TRANSCRIPTION_MODEL").unwrap_or(WHISPER_1.to_string());
+
+        Ok(Self {
+            base_url: std::env::var("AI_BASE_URL")
+                .ok()
+                .filter(|url| !url.is_empty()),
+            free_chat_models: model_list("FREE_CHAT_MODELS", &chat_model),
+            free_transcription_models: model_list(
+                "FREE_TRANSCRIPTION_MODELS",
+                &transcription_model,
+            ),
+            chat_model,
+            transcription_model,
+            #[cfg(feature = "local-whisper")]
+            local_whisper: LocalWhisper::from_env()?,
+        })
+    }
+
+    pub fn default_chat_model(&self) -> &str {
+        &self.chat_model
+    }
+
+    pub fn default_transcription_model(&self) -> &str {
+        &self.transcription_model
+    }
+
+    pub fn free_chat_models(&self) -> &[String] {
+        &self.free_chat_models
+    }
+
+    pub fn free_transcription_models(&self) -> &[String] {
+        &self.free_transcription_models
+    }
+
+    /// The chat model used for the user. Models the operator didn't approve are only used with
+    /// the user's own key.
+    pub fn chat_model(&self, user: &User) -> String {
+        allowed_model(
+            user.chat_model.as_deref(),
+            &self.chat_model,
+            &self.free_chat_models,
+            user.openai_api_key.is_some(),
+        )
+    }
+
+    pub fn transcription_model(&self, user: &User) -> String {
+        allowed_model(
+            user.transcription_model.as_deref(),
+            &self.transcription_model,
+            &self.free_transcription_models,
+            user.openai_api_key.is_some(),
+        )
+    }
+
+    /// The OpenAI-compatible server requests are sent to instead of OpenAI, if any. A custom
+    /// server only ever receives the user's own key, never the operator's.
+    fn custom_endpoint(&self, user: &User, own_key: bool) -> Option<Endpoint> {
+        match &user.ai_base_url {
+            Some(base_url) if own_key => Some(Endpoint {
+                base_url: base_url.clone(),
+                user_supplied: true,
+            }),
+            _ => self.base_url.clone().map(|base_url| Endpoint {
+                base_url,
+                user_supplied: false,
+            }),
+        }
+    }
+
+    /// The server the user's requests are sent to.
+    pub fn endpoint(&self, user: &User) -> Endpoint {
+        self.custom_endpoint(user, user.openai_api_key.is_some())
+            .unwrap_or_else(Endpoint::openai)
+    }
+
+    /// The server a key the user sends will be used with, so it can be checked there.
+    pub fn key_endpoint(&self, user: &User) -> Endpoint {
+        self.custom_endpoint(user, true)
+            .unwrap_or_else(Endpoint::openai)
+    }
+
+    pub fn provider(&self, user: &User, api_key: String) -> Provider {
+        #[allow(unused_mut)]
+        let mut provider = match self.custom_endpoint(user, user.openai_api_key.is_some()) {
+            Some(endpoint) => {
+                let provider = CompatibleProvider {
+                    http: endpoint.client(),
+                    base_url: endpoint.base_url,
+                    api_key,
+                    chat_model: self.chat_model(user),
+                    transcription_model: self.transcription_model(user),
+                };
+
+                Provider {
+                    transcriber: Box::new(provider.clone()),
+                    writer: Box::new(provider),
+                }
+            }
+            None => {
+                // `openai-api-rs` drops the detected language from transcriptions, so they go
+                // through the plain HTTP client against OpenAI instead.
+                let transcriber = CompatibleProvider {
+                    base_url: openai_base_url(),
+                    api_key: api_key.clone(),
+                    chat_model: self.chat_model(user),
+                    transcription_model: self.transcription_model(user),
+                    http: reqwest::Client::new(),
+                };
+
+                Provider {
+                    transcriber: Box::new(transcriber),
+                    writer: Box::new(OpenAiProvider {
+                        api_key,
+                        chat_model: self.chat_model(user),
+                    }),
+                }
+            }
+        };
+
+        #[cfg(feature = "local-whisper")]
+        if let Some(local_whisper) = &self.local_whisper {
+            provider.transcriber = Box::new(local_whisper.clone());
+        }
+
+        provider
+    }
+}
+
+fn model_list(var: &str, default: &str) -> Vec<String> {
+    let mut models: Vec<String> = std::env::var(var)
+        .unwrap_or_default()
+        .split(',')
+        .map(|model| model.trim().to_string())
+        .filter(|model| !model.is_empty())
+        .collect();
+
+    if !models.iter().any(|model| model == default) {
+        models.insert(0, default.to_string());
+    }
+
+    models
+}
+
+fn allowed_model(selected: Option<&str>, default: &str, free: &[String], own_key: bool) -> String {
+    match selected {
+        Some(model) if own_key || free.iter().any(|free| free == model) => model.to_string(),
+        _ => default.to_string(),
+    }
+}
+
+/// Recognizes error responses that reject the requested model.
+fn model_rejection(model: &str, status: u16, body: &str) -> Option<ModelRejected> {
+    let json = serde_json::from_str::<Value>(body).ok();
+    let error = json.as_ref().and_then(|json| json.get("error"));
+
+    let code = error
+        .and_then(|error| error.get("code"))
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let message = error
+        .and_then(|error| error.get("message"))
+        .and_then(Value::as_str)
+        .unwrap_or(body)
+        .trim();
+
+    let mentions_model = message.to_lowercase().contains("model");
+    if code == "model_not_found" || (matches!(status, 400 | 403 | 404) && mentions_model) {
+        Some(ModelRejected {
+            model: model.to_string(),
+            reason: message.to_string(),
+        })
+    } else {
+        None
+    }
+}
+
+/// Writes posts with OpenAI through `openai-api-rs`.
+#[derive(Clone)]
+pub struct OpenAiProvider {
+    api_key: String,
+    chat_model: String,
+}
+
+impl OpenAiProvider {
+    fn client(&self) -> anyhow::Result<OpenAIClient> {
+        OpenAIClient::builder()
+            .with_api_key(self.api_key.clone())
+            .build()
+            .ok()
+            .context("Could not create the OpenAI client")
+    }
+}
+
+/// `openai-api-rs` reports failed requests as "<status>: <body>".
+fn api_error(model: &str, e: APIError) -> anyhow::Error {
+    if let APIError::CustomError { message } = &e {
+        if let Some((status, body)) = message.split_once(": ") {
+            let status = status
+                .split_whitespace()
+                .next()
+                .and_then(|status| status.parse().ok())
+                .unwrap_or_default();
+
+            if let Some(rejected) = model_rejection(model, status, body) {
+                return rejected.into();
+            }
+        }
+    }
+
+    e.into()
+}
+
+#[async_trait]
+impl Writer for OpenAiProvider {
+    async fn complete(&self, request: &CompletionRequest<'_>) -> anyhow::Result<Vec<String>> {
+        let mut req = ChatCompletionRequest::new(self.chat_model.clone(), messages(request))
+            .n(request.variants);
+        if request.json {
+            req = req.response_format(json!({ "type": "json_object" }));
+        }
+
+        let result = self
+            .client()?
+            .chat_completion(req)
+            .await
+            .map_err(|e| api_error(&self.chat_model, e))?;
+
+        Ok(result
+            .choices
+            .into_iter()
+            .map(|choice| choice.message.content.unwrap_or("No content".to_string()))
+            .collect())
+    }
+}
+
+fn messages(request: &CompletionRequest<'_>) -> Vec<ChatCompletionMessage> {
+    vec![
+        ChatCompletionMessage {
+            role: MessageRole::system,
+            content: chat_completion::Content::Text(request.instructions.to_string()),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        },
+        ChatCompletionMessage {
+            role: MessageRole::user,
+            content: chat_completion::Content::Text(request.text.to_string()),
+            name: Some(request.from_user.to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+        },
+    ]
+}
+
+/// Talks to any server implementing OpenAI's `/chat/completions` and `/audio/transcriptions`.
+#[derive(Clone)]
+pub struct CompatibleProvider {
+    base_url: String,
+    api_key: String,
+    chat_model: String,
+    transcription_model: String,
+    http: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct TranscriptionResponse {
+    text: String,
+    #[serde(default)]
+    language: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatMessage,
+}
+
+#[derive(Deserialize)]
+struct ChatMessage {
+    content: Option<String>,
+}
+
+impl CompatibleProvider {
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url.trim_end_matches('/'), path)
+    }
+
+    async fn send<T: serde::de::DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+        model: &str,
+    ) -> anyhow::Result<T> {
+        let response = request.bearer_auth(&self.api_key).send().await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            if let Some(rejected) = model_rejection(model, status.as_u16(), &body) {
+                return Err(rejected.into());
+            }
+            bail!("status {}: {}", status, body);
+        }
+
+        Ok(response.json().await?)
+    }
+}
+
+#[async_trait]
+impl Transcriber for CompatibleProvider {
+    async fn transcribe(&self, path: &Path, language: Option<&str>) -> anyhow::Result<Transcript> {
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or("audio.mp3".to_string());
+        let bytes = tokio::fs::read(path).await?;
+
+        let mut form = Form::new()
+            .text("model", self.transcription_model.clone())
+            .text("response_format", "verbose_json")
+            .part("file", Part::bytes(bytes).file_name(file_name));
+        if let Some(language) = language {
+            form = form.text("language", language.to_string());
+        }
+
+        let response: TranscriptionResponse = self
+            .send(
+                self.http
+                    .post(self.url("audio/transcriptions"))
+                    .multipart(form),
+                &self.transcription_model,
+            )
+            .await?;
+
+        Ok(Transcript {
+            text: response.text,
+            language: response.language.or(language.map(str::to_string)),
+        })
+    }
+}
+
+#[async_trait]
+impl Writer for CompatibleProvider {
+    async fn complete(&self, request: &CompletionRequest<'_>) -> anyhow::Result<Vec<String>> {
+        let mut body = json!({
+            "model": self.chat_model,
+            "n": request.variants,
+            "messages": [
+                { "role": "system", "content": request.instructions },
+                { "role": "user", "content": request.text, "name": request.from_user },
+            ],
+        });
+        if request.json {
+            body["response_format"] = json!({ "type": "json_object" });
+        }
+
+        let response: ChatResponse = self
+            .send(
+                self.http.post(self.url("chat/completions")).json(&body),
+                &self.chat_model,
+            )
+            .await?;
+
+        Ok(response
+            .choices
+            .into_iter()
+            .map(|choice| choice.message.content.unwrap_or("No content".to_string()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::OffsetDateTime;
+
+    use super::*;
+
+    fn config(base_url: Option<&str>) -> ProviderConfig {
+        ProviderConfig {
+            base_url: base_url.map(str::to_string),
+            chat_model: GPT4_O_MINI.to_string(),
+            transcription_model: WHISPER_1.to_string(),
+            free_chat_models: vec![GPT4_O_MINI.to_string()],
+            free_transcription_models: vec![WHISPER_1.to_string()],
+            #[cfg(feature = "local-whisper")]
+            local_whisper: None,
+        }
+    }
+
+    fn user(ai_base_url: Option<&str>, openai_api_key: Option<&str>) -> User {
+        User {
+            telegram_id: 1,
+            username: "alice".to_string(),
+            typefully_api_key: None,
+            openai_api_key: openai_api_key.map(str::to_string),
+            style_profile: "default".to_string(),
+            created_at: OffsetDateTime::UNIX_EPOCH,
+            default_schedule: None,
+            utc_offset_minutes: 0,
+            thread_enabled: false,
+            destination: "typefully".to_string(),
+            webhook_url: None,
+            variant_count: 1,
+            ai_base_url: ai_base_url.map(str::to_string),
+            chat_model: Some("gpt-4o".to_string()),
+            transcription_model: None,
+            transcription_language: None,
+            output_language: None,
+            inbox_enabled: false,
+            share_enabled: false,
+        }
+    }
+
+    fn endpoint(base_url: &str, user_supplied: bool) -> Endpoint {
+        Endpoint {
+            base_url: base_url.to_string(),
+            user_supplied,
+        }
+    }
+
+    #[test]
+    fn custom_server_only_receives_own_key() {
+        let operator = config(Some("https://operator/v1"));
+
+        assert_eq!(
+            operator.endpoint(&user(Some("https://user/v1"), Some("sk-own"))),
+            endpoint("https://user/v1", true)
+        );
+        assert_eq!(
+            operator.endpoint(&user(Some("https://user/v1"), None)),
+            endpoint("https://operator/v1", false)
+        );
+        assert_eq!(
+            config(None).endpoint(&user(None, None)),
+            endpoint(&openai_base_url(), false)
+        );
+    }
+
+    #[test]
+    fn keys_are_checked_where_they_are_used() {
+        // A new key is the user's own key, so it goes to their server even before it's saved
+        assert_eq!(
+            config(None).key_endpoint(&user(Some("https://user/v1"), None)),
+            endpoint("https://user/v1", true)
+        );
+        assert_eq!(
+            config(Some("https://operator/v1")).key_endpoint(&user(None, None)),
+            endpoint("https://operator/v1", false)
+        );
+        assert_eq!(
+            config(None).key_endpoint(&user(None, None)),
+            endpoint(&openai_base_url(), false)
+        );
+    }
+
+    #[tokio::test]
+    async fn user_servers_must_be_public() {
+        assert!(endpoint("https://169.254.169.254/v1", true)
+            .check()
+            .await
+            .is_err());
+        // The operator may use a server on the same machine
+        assert!(endpoint("http://localhost:8080/v1", false)
+            .check()
+            .await
+            .is_ok());
+    }
+
+    #[test]
+    fn unapproved_models_need_own_key() {
+        let config = config(None);
+
+        assert_eq!(config.chat_model(&user(None, Some("sk-own"))), "gpt-4o");
+        assert_eq!(config.chat_model(&user(None, None)), GPT4_O_MINI);
+    }
+
+    #[test]
+    fn rejected_models_are_recognized() {
+        let body = r#"{"error": {"message": "The model `gpt-5` does not exist", "code": "model_not_found"}}"#;
+
+        let rejected = model_rejection("gpt-5", 404, body).unwrap();
+        assert_eq!(rejected.model, "gpt-5");
+        assert_eq!(rejected.reason, "The model `gpt-5` does not exist");
+        assert!(model_rejection("gpt-5", 500, "Internal error").is_none());
+    }
+}
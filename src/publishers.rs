@@ -0,0 +1,227 @@
+use std::{fmt, path::PathBuf, str::FromStr, time::Duration};
+
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use serde_json::json;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{
+    db::User,
+    schedule::Schedule,
+    typefully::{CreateDraft, TypefullyClient, TypefullyError},
+    urls::{self, UrlPolicy},
+};
+
+const DEFAULT_MARKDOWN_DIR: &str = "./posts";
+
+/// How long a webhook may take to answer.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Drafts open in the Typefully editor at this URL followed by their id.
+const TYPEFULLY_DRAFT_URL: &str = "https://typefully.com/?d=";
+
+/// A post that is ready to leave the bot.
+pub struct Post<'a> {
+    pub telegram_id: i64,
+    pub content: &'a str,
+    pub schedule: Schedule,
+    /// Whether the destination should create a public share link.
+    pub share: bool,
+}
+
+/// Where a published post can be found, if the destination tells us.
+#[derive(Debug, Default)]
+pub struct Published {
+    pub draft_id: Option<i64>,
+    /// Link to edit the draft.
+    pub edit_url: Option<String>,
+    /// Public link to the draft, only set if sharing was requested.
+    pub share_url: Option<String>,
+}
+
+#[async_trait]
+pub trait Publisher: Send + Sync {
+    /// Human readable name of the destination, used in messages to the user.
+    fn name(&self) -> &'static str;
+
+    async fn publish(&self, post: &Post<'_>) -> anyhow::Result<Published>;
+}
+
+/// Where a user's approved posts are sent to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Destination {
+    Typefully,
+    Markdown,
+    Webhook,
+}
+
+impl Destination {
+    pub fn publisher(&self, user: &User) -> anyhow::Result<Box<dyn Publisher>> {
+        match self {
+            Self::Typefully => {
+                let api_key = user.typefully_api_key.clone().context(
+                    "You have no Typefully API key set. Please set one with /settypefullykey.",
+                )?;
+
+                Ok(Box::new(TypefullyPublisher::new(api_key)))
+            }
+            Self::Markdown => Ok(Box::new(MarkdownPublisher::from_env())),
+            Self::Webhook => {
+                let url = user.webhook_url.clone().context(
+                    "You have no webhook URL set. Please set one with /destination webhook <url>.",
+                )?;
+
+                Ok(Box::new(WebhookPublisher::new(url)))
+            }
+        }
+    }
+}
+
+impl FromStr for Destination {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "typefully" => Ok(Self::Typefully),
+            "markdown" => Ok(Self::Markdown),
+            "webhook" => Ok(Self::Webhook),
+            other => bail!("Unknown destination '{}'", other),
+        }
+    }
+}
+
+impl fmt::Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Typefully => write!(f, "typefully"),
+            Self::Markdown => write!(f, "markdown"),
+            Self::Webhook => write!(f, "webhook"),
+        }
+    }
+}
+
+pub struct TypefullyPublisher {
+    client: TypefullyClient,
+}
+
+impl TypefullyPublisher {
+    pub fn new(api_key: String) -> Self {
+        Self {
+            client: TypefullyClient::new(api_key),
+        }
+    }
+
+    /// Checks the API key against an endpoint without side effects.
+    pub async fn verify(&self) -> Result<(), TypefullyError> {
+        self.client.notifications().await.map(|_| ())
+    }
+}
+
+#[async_trait]
+impl Publisher for TypefullyPublisher {
+    fn name(&self) -> &'static str {
+        "Typefully"
+    }
+
+    async fn publish(&self, post: &Post<'_>) -> anyhow::Result<Published> {
+        let draft = CreateDraft {
+            content: post.content.to_string(),
+            schedule_date: post.schedule.typefully_value(),
+            share: post.share.then_some(true),
+            ..Default::default()
+        };
+
+        let draft = self.client.create_draft(&draft).await?;
+
+        Ok(Published {
+            draft_id: Some(draft.id),
+            edit_url: Some(format!("{}{}", TYPEFULLY_DRAFT_URL, draft.id)),
+            share_url: draft.share_url,
+        })
+    }
+}
+
+/// Writes every post as a Markdown file into a per-user folder, e.g. for a static blog pipeline.
+pub struct MarkdownPublisher {
+    dir: PathBuf,
+}
+
+impl MarkdownPublisher {
+    pub fn from_env() -> Self {
+        let dir =
+            std::env::var("MARKDOWN_DIR").unwrap_or_else(|_| DEFAULT_MARKDOWN_DIR.to_string());
+
+        Self {
+            dir: PathBuf::from(dir),
+        }
+    }
+}
+
+#[async_trait]
+impl Publisher for MarkdownPublisher {
+    fn name(&self) -> &'static str {
+        "Markdown"
+    }
+
+    async fn publish(&self, post: &Post<'_>) -> anyhow::Result<Published> {
+        let dir = self.dir.join(post.telegram_id.to_string());
+        tokio::fs::create_dir_all(&dir).await?;
+
+        let now = OffsetDateTime::now_utc();
+        let mut front_matter = format!("---\ncreated_at: {}\n", now.format(&Rfc3339)?);
+        if let Some(schedule_date) = post.schedule.typefully_value() {
+            front_matter.push_str(&format!("schedule: {}\n", schedule_date));
+        }
+        front_matter.push_str("---\n\n");
+
+        let path = dir.join(format!("{}.md", now.unix_timestamp_nanos()));
+        tokio::fs::write(&path, format!("{}{}\n", front_matter, post.content)).await?;
+
+        Ok(Published::default())
+    }
+}
+
+/// Posts every post as JSON to a user-provided URL.
+pub struct WebhookPublisher {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl WebhookPublisher {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            client: urls::public_client(WEBHOOK_TIMEOUT),
+        }
+    }
+}
+
+#[async_trait]
+impl Publisher for WebhookPublisher {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn publish(&self, post: &Post<'_>) -> anyhow::Result<Published> {
+        // Checked again since the allowed hosts may have changed since the URL was set
+        let url = UrlPolicy::webhooks().check(&self.url).await?;
+
+        let response = self
+            .client
+            .post(url)
+            .json(&json!({
+                "telegram_id": post.telegram_id,
+                "content": post.content,
+                "schedule_date": post.schedule.typefully_value(),
+                "created_at": OffsetDateTime::now_utc().format(&Rfc3339)?,
+            }))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            bail!("The webhook responded with {}.", response.status());
+        }
+
+        Ok(Published::default())
+    }
+}
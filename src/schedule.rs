@@ -0,0 +1,304 @@
+use anyhow::{bail, Context, Result};
+use time::{
+    format_description::well_known::Rfc3339, macros::format_description, Date, Duration,
+    OffsetDateTime, PrimitiveDateTime, Time, UtcOffset,
+};
+
+const NEXT_FREE_SLOT: &str = "next-free-slot";
+const UNSCHEDULED: &str = "none";
+
+/// When a single draft should be published by Typefully.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    Unscheduled,
+    NextFreeSlot,
+    At(OffsetDateTime),
+}
+
+/// A user's schedule preference, resolved into a [`Schedule`] when a draft is approved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefaultSchedule {
+    Unscheduled,
+    NextFreeSlot,
+    DailyAt(Time),
+}
+
+impl Schedule {
+    /// Parses `none`, `next-free-slot`, `HH:MM`, `today HH:MM`, `tomorrow HH:MM` or `YYYY-MM-DD HH:MM`
+    /// in the user's timezone.
+    pub fn parse(input: &str, now: OffsetDateTime, offset: UtcOffset) -> Result<Self> {
+        let input = input.trim().to_lowercase();
+        let now = now.to_offset(offset);
+
+        match input.as_str() {
+            UNSCHEDULED => return Ok(Self::Unscheduled),
+            NEXT_FREE_SLOT => return Ok(Self::NextFreeSlot),
+            _ => {}
+        }
+
+        let (day, time) = match input.split_once(' ') {
+            Some((day, time)) => (Some(day), time),
+            None => (None, input.as_str()),
+        };
+        let time = parse_time(time)?;
+
+        let date = match day {
+            None => return Ok(DefaultSchedule::DailyAt(time).resolve(now, offset)),
+            Some("today") => now.date(),
+            Some("tomorrow") => now.date() + Duration::days(1),
+            Some(day) => Date::parse(day, format_description!("[year]-[month]-[day]"))
+                .with_context(|| format!("Unknown date '{}', use YYYY-MM-DD", day))?,
+        };
+
+        let at = PrimitiveDateTime::new(date, time).assume_offset(offset);
+        if at <= now {
+            bail!("That time is in the past");
+        }
+
+        Ok(Self::At(at))
+    }
+
+    /// The value for Typefully's `schedule-date` field, if the draft should be scheduled.
+    pub fn typefully_value(&self) -> Option<String> {
+        match self {
+            Self::Unscheduled => None,
+            Self::NextFreeSlot => Some(NEXT_FREE_SLOT.to_string()),
+            Self::At(at) => at.to_offset(UtcOffset::UTC).format(&Rfc3339).ok(),
+        }
+    }
+
+    pub fn to_db_value(self) -> String {
+        self.typefully_value()
+            .unwrap_or_else(|| UNSCHEDULED.to_string())
+    }
+
+    pub fn from_db_value(value: &str) -> Self {
+        match value {
+            NEXT_FREE_SLOT => Self::NextFreeSlot,
+            value => OffsetDateTime::parse(value, &Rfc3339)
+                .map(Self::At)
+                .unwrap_or(Self::Unscheduled),
+        }
+    }
+
+    pub fn describe(&self, offset: UtcOffset) -> String {
+        match self {
+            Self::Unscheduled => "not scheduled".to_string(),
+            Self::NextFreeSlot => "next free slot".to_string(),
+            Self::At(at) => format!(
+                "{} (UTC{})",
+                at.to_offset(offset)
+                    .format(format_description!("[year]-[month]-[day] [hour]:[minute]"))
+                    .unwrap_or_default(),
+                describe_offset(offset)
+            ),
+        }
+    }
+}
+
+impl DefaultSchedule {
+    /// Parses `none`, `next-free-slot` or a daily time like `09:00`.
+    pub fn parse(input: &str) -> Result<Self> {
+        match input.trim().to_lowercase().as_str() {
+            UNSCHEDULED => Ok(Self::Unscheduled),
+            NEXT_FREE_SLOT => Ok(Self::NextFreeSlot),
+            time => parse_time(time).map(Self::DailyAt),
+        }
+    }
+
+    pub fn to_db_value(self) -> Option<String> {
+        match self {
+            Self::Unscheduled => None,
+            Self::NextFreeSlot => Some(NEXT_FREE_SLOT.to_string()),
+            Self::DailyAt(time) => time.format(format_description!("[hour]:[minute]")).ok(),
+        }
+    }
+
+    pub fn from_db_value(value: Option<&str>) -> Self {
+        value
+            .and_then(|value| Self::parse(value).ok())
+            .unwrap_or(Self::Unscheduled)
+    }
+
+    /// Turns the preference into a concrete schedule, picking the next occurrence of a daily time.
+    pub fn resolve(&self, now: OffsetDateTime, offset: UtcOffset) -> Schedule {
+        match self {
+            Self::Unscheduled => Schedule::Unscheduled,
+            Self::NextFreeSlot => Schedule::NextFreeSlot,
+            Self::DailyAt(time) => {
+                let now = now.to_offset(offset);
+                let today = now.replace_time(*time);
+
+                if today > now {
+                    Schedule::At(today)
+                } else {
+                    Schedule::At(today + Duration::days(1))
+                }
+            }
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Unscheduled => "not scheduled".to_string(),
+            Self::NextFreeSlot => "next free slot".to_string(),
+            Self::DailyAt(_) => format!("daily at {}", self.to_db_value().unwrap_or_default()),
+        }
+    }
+}
+
+/// Parses a UTC offset like `+02:00`, `-5` or `UTC+1`.
+pub fn parse_utc_offset(input: &str) -> Result<UtcOffset> {
+    let input = input.trim().to_uppercase();
+    let input = input.strip_prefix("UTC").unwrap_or(&input);
+
+    if input.is_empty() {
+        return Ok(UtcOffset::UTC);
+    }
+
+    // Phone keyboards often replace `-` with a Unicode minus or dash
+    let (sign, rest) = if let Some(rest) = input.strip_prefix('+') {
+        (1, rest)
+    } else if let Some(rest) = input.strip_prefix(['-', '−', '–']) {
+        (-1, rest)
+    } else {
+        bail!("The offset has to start with + or -, e.g. +02:00")
+    };
+
+    let (hours, minutes) = rest.split_once(':').unwrap_or((rest, "0"));
+    let hours: i8 = hours.parse().context("Invalid hours in offset")?;
+    let minutes: i8 = minutes.parse().context("Invalid minutes in offset")?;
+
+    UtcOffset::from_hms(sign * hours, sign * minutes, 0).context("Offset out of range")
+}
+
+pub fn offset_from_minutes(minutes: i64) -> UtcOffset {
+    UtcOffset::from_whole_seconds((minutes * 60) as i32).unwrap_or(UtcOffset::UTC)
+}
+
+pub fn describe_offset(offset: UtcOffset) -> String {
+    let (hours, minutes, _) = offset.as_hms();
+    let sign = if offset.is_negative() { '-' } else { '+' };
+
+    format!("{}{:02}:{:02}", sign, hours.abs(), minutes.abs())
+}
+
+fn parse_time(input: &str) -> Result<Time> {
+    let (hours, minutes) = input.split_once(':').unwrap_or((input, "0"));
+    let hours = hours
+        .parse()
+        .with_context(|| format!("Unknown time '{}', use HH:MM", input))?;
+    let minutes = minutes
+        .parse()
+        .with_context(|| format!("Unknown time '{}', use HH:MM", input))?;
+
+    Time::from_hms(hours, minutes, 0).with_context(|| format!("Invalid time '{}'", input))
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::{datetime, offset, time};
+
+    use super::*;
+
+    const NOW: OffsetDateTime = datetime!(2024-03-10 14:30 UTC);
+
+    #[test]
+    fn parses_utc_offsets() {
+        assert_eq!(parse_utc_offset("+02:00").unwrap(), offset!(+2));
+        assert_eq!(parse_utc_offset("-5").unwrap(), offset!(-5));
+        assert_eq!(parse_utc_offset("UTC+1").unwrap(), offset!(+1));
+        assert_eq!(parse_utc_offset("utc").unwrap(), UtcOffset::UTC);
+        assert_eq!(parse_utc_offset("+05:30").unwrap(), offset!(+5:30));
+        assert_eq!(parse_utc_offset("−02:00").unwrap(), offset!(-2));
+        assert_eq!(parse_utc_offset("–3").unwrap(), offset!(-3));
+    }
+
+    #[test]
+    fn rejects_invalid_utc_offsets() {
+        assert!(parse_utc_offset("02:00").is_err());
+        assert!(parse_utc_offset("ä").is_err());
+        assert!(parse_utc_offset("+").is_err());
+        assert!(parse_utc_offset("+2:xx").is_err());
+        assert!(parse_utc_offset("+30").is_err());
+    }
+
+    #[test]
+    fn parses_keywords() {
+        assert_eq!(
+            Schedule::parse("none", NOW, UtcOffset::UTC).unwrap(),
+            Schedule::Unscheduled
+        );
+        assert_eq!(
+            Schedule::parse(" Next-Free-Slot ", NOW, UtcOffset::UTC).unwrap(),
+            Schedule::NextFreeSlot
+        );
+    }
+
+    #[test]
+    fn parses_today_and_tomorrow_in_the_users_timezone() {
+        assert_eq!(
+            Schedule::parse("today 18:00", NOW, offset!(+2)).unwrap(),
+            Schedule::At(datetime!(2024-03-10 18:00 +2))
+        );
+        assert_eq!(
+            Schedule::parse("tomorrow 9:00", NOW, offset!(+2)).unwrap(),
+            Schedule::At(datetime!(2024-03-11 09:00 +2))
+        );
+    }
+
+    #[test]
+    fn parses_dates() {
+        assert_eq!(
+            Schedule::parse("2024-04-01 08:15", NOW, UtcOffset::UTC).unwrap(),
+            Schedule::At(datetime!(2024-04-01 08:15 UTC))
+        );
+        assert!(Schedule::parse("01.04.2024 08:15", NOW, UtcOffset::UTC).is_err());
+    }
+
+    #[test]
+    fn rejects_times_in_the_past() {
+        assert!(Schedule::parse("today 14:00", NOW, UtcOffset::UTC).is_err());
+        assert!(Schedule::parse("2024-03-01 10:00", NOW, UtcOffset::UTC).is_err());
+        // 15:00 UTC has already passed in UTC+2
+        assert!(Schedule::parse("today 15:00", NOW, offset!(+2)).is_err());
+    }
+
+    #[test]
+    fn bare_time_picks_the_next_occurrence() {
+        assert_eq!(
+            Schedule::parse("16:00", NOW, UtcOffset::UTC).unwrap(),
+            Schedule::At(datetime!(2024-03-10 16:00 UTC))
+        );
+        assert_eq!(
+            Schedule::parse("09:00", NOW, UtcOffset::UTC).unwrap(),
+            Schedule::At(datetime!(2024-03-11 09:00 UTC))
+        );
+        assert!(Schedule::parse("25:00", NOW, UtcOffset::UTC).is_err());
+    }
+
+    #[test]
+    fn resolves_default_schedules() {
+        assert_eq!(
+            DefaultSchedule::Unscheduled.resolve(NOW, UtcOffset::UTC),
+            Schedule::Unscheduled
+        );
+        assert_eq!(
+            DefaultSchedule::NextFreeSlot.resolve(NOW, UtcOffset::UTC),
+            Schedule::NextFreeSlot
+        );
+        assert_eq!(
+            DefaultSchedule::DailyAt(time!(15:00)).resolve(NOW, UtcOffset::UTC),
+            Schedule::At(datetime!(2024-03-10 15:00 UTC))
+        );
+        assert_eq!(
+            DefaultSchedule::DailyAt(time!(14:30)).resolve(NOW, UtcOffset::UTC),
+            Schedule::At(datetime!(2024-03-11 14:30 UTC))
+        );
+        // 14:30 UTC is already 23:30 in UTC+9, so 09:00 is the next morning there
+        assert_eq!(
+            DefaultSchedule::DailyAt(time!(09:00)).resolve(NOW, offset!(+9)),
+            Schedule::At(datetime!(2024-03-11 09:00 +9))
+        );
+    }
+}
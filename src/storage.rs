@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use futures::future::{BoxFuture, FutureExt};
+use serde::{de::DeserializeOwned, Serialize};
+use teloxide::{dispatching::dialogue::Storage, types::ChatId};
+
+use crate::db::Database;
+
+/// Stores dialogue state as JSON in the bot's SQLite database.
+impl<D> Storage<D> for Database
+where
+    D: Serialize + DeserializeOwned + Send + 'static,
+{
+    type Error = anyhow::Error;
+
+    fn remove_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<'static, Result<(), Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        async move {
+            if !self.remove_dialogue_state(chat_id.0).await? {
+                anyhow::bail!("Dialogue for chat {} not found", chat_id.0);
+            }
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn update_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+        dialogue: D,
+    ) -> BoxFuture<'static, Result<(), Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        async move {
+            let state = serde_json::to_string(&dialogue)?;
+            self.set_dialogue_state(chat_id.0, &state).await
+        }
+        .boxed()
+    }
+
+    fn get_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<'static, Result<Option<D>, Self::Error>> {
+        async move {
+            match self.get_dialogue_state(chat_id.0).await? {
+                Some(state) => Ok(Some(serde_json::from_str(&state)?)),
+                None => Ok(None),
+            }
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,140 @@
+use anyhow::{bail, Result};
+
+pub const DEFAULT_STYLE: &str = "rewrite";
+pub const FORMAT_ONLY_STYLE: &str = "format";
+
+const MAX_NAME_LENGTH: usize = 32;
+
+const SUMMARY_INSTRUCTIONS: &str = r#"You are an expert for social media posts & working with texts in any language. Sometimes you get a text in German, English, Spanish or other languages.
+
+You get a text from a user and you should make a social media draft out of it.
+Your responses should ALWAYS be IN the language of the USERS TEXT.
+
+Whenever you get a text you should do the following:
+
+1. Properly format the given text, making it readable, by adding appropriate commas, breaks etc.
+2. Make sure the post has a punchline.
+3. Don't use hashtags.
+4. Don't overdo it with emojis.
+5. Make sure the post is not too long.
+6. Make sure the post is not too short.
+7. Make sure the post is not too boring.
+8. Make sure you don't use typical AI words like: driven, motivated, inspired, delve, into the future
+"#;
+
+const FORMAT_ONLY_INSTRUCTIONS: &str = r#"You are an expert for formatting text in any language. Sometimes you get a text in German, English, Spanish or other languages.
+
+You get a text from a user and you should format it for social media.
+Your responses should ALWAYS be IN the language of the USERS TEXT.
+
+Whenever you get a text you should do the following:
+
+1. Properly format the given text, making it readable, by adding appropriate commas, breaks etc.
+2. Don't change the content or meaning of the text.
+3. Don't add or remove any information.
+4. Don't use hashtags.
+5. Don't add emojis.
+6. Keep the original tone and style of the text.
+"#;
+
+const FOUNDER_INSTRUCTIONS: &str = r#"You are a ghostwriter for startup founders & working with texts in any language. Sometimes you get a text in German, English, Spanish or other languages.
+
+You get a text from a user and you should make a LinkedIn-style post out of it.
+Your responses should ALWAYS be IN the language of the USERS TEXT.
+
+Whenever you get a text you should do the following:
+
+1. Open with a short, personal hook in the first line.
+2. Tell it as a lesson learned from building the company.
+3. Use short paragraphs with a blank line between them.
+4. End with a question that invites the reader to share their experience.
+5. Don't use hashtags.
+6. Use at most one emoji.
+7. Make sure you don't use typical AI words like: driven, motivated, inspired, delve, into the future
+"#;
+
+const TECHNICAL_INSTRUCTIONS: &str = r#"You are a senior engineer writing about your work & working with texts in any language. Sometimes you get a text in German, English, Spanish or other languages.
+
+You get a text from a user and you should make a social media draft out of it.
+Your responses should ALWAYS be IN the language of the USERS TEXT.
+
+Whenever you get a text you should do the following:
+
+1. Keep a dry, matter-of-fact tone.
+2. Be precise and keep technical terms as they are.
+3. Cut filler words, marketing language and exaggerations.
+4. Don't use hashtags.
+5. Don't use emojis.
+6. Make sure the post is not too long.
+"#;
+
+/// Built-in profiles every user can select. Their names can't be used for custom profiles.
+const BUILT_IN_STYLES: &[(&str, &str)] = &[
+    (DEFAULT_STYLE, SUMMARY_INSTRUCTIONS),
+    (FORMAT_ONLY_STYLE, FORMAT_ONLY_INSTRUCTIONS),
+    ("linkedin-founder", FOUNDER_INSTRUCTIONS),
+    ("dry-technical", TECHNICAL_INSTRUCTIONS),
+];
+
+/// A named system prompt used to turn a transcript into a post.
+#[derive(Debug, Clone)]
+pub struct StyleProfile {
+    pub name: String,
+    pub instructions: String,
+}
+
+impl StyleProfile {
+    pub fn custom(name: String, instructions: String) -> Self {
+        Self { name, instructions }
+    }
+
+    pub fn built_in(name: &str) -> Option<Self> {
+        BUILT_IN_STYLES
+            .iter()
+            .find(|(built_in, _)| *built_in == name)
+            .map(|(name, instructions)| Self {
+                name: name.to_string(),
+                instructions: instructions.to_string(),
+            })
+    }
+
+    pub fn built_ins() -> Vec<Self> {
+        BUILT_IN_STYLES
+            .iter()
+            .filter_map(|(name, _)| Self::built_in(name))
+            .collect()
+    }
+
+    pub fn default_profile() -> Self {
+        Self::built_in(DEFAULT_STYLE).expect("default style is built in")
+    }
+}
+
+/// Turns user input like "LinkedIn founder" into a profile name like `linkedin-founder`.
+pub fn normalize_name(input: &str) -> Result<String> {
+    let name = input
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-")
+        .to_lowercase();
+
+    if name.is_empty() {
+        bail!("Please give the style a name");
+    }
+
+    if name.chars().count() > MAX_NAME_LENGTH {
+        bail!(
+            "Style names can be at most {} characters long",
+            MAX_NAME_LENGTH
+        );
+    }
+
+    if !name
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!("Style names may only contain letters, digits, '-' and '_'");
+    }
+
+    Ok(name)
+}
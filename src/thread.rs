@@ -0,0 +1,181 @@
+/// Typefully splits a draft into tweets wherever it finds four consecutive newlines.
+pub const THREAD_SEPARATOR: &str = "\n\n\n\n";
+
+const DEFAULT_MAX_TWEET_LENGTH: usize = 280;
+
+pub fn max_tweet_length() -> usize {
+    std::env::var("MAX_TWEET_LENGTH")
+        .ok()
+        .and_then(|length| length.parse().ok())
+        .filter(|&length| length > 0)
+        .unwrap_or(DEFAULT_MAX_TWEET_LENGTH)
+}
+
+pub fn join(tweets: &[String]) -> String {
+    tweets.join(THREAD_SEPARATOR)
+}
+
+pub fn tweet_count(content: &str) -> usize {
+    content.split(THREAD_SEPARATOR).count()
+}
+
+/// Makes sure no tweet exceeds `max_length` characters, splitting at sentence and word
+/// boundaries where possible.
+pub fn enforce_max_length(tweets: Vec<String>, max_length: usize) -> Vec<String> {
+    tweets
+        .into_iter()
+        .map(|tweet| tweet.trim().to_string())
+        .filter(|tweet| !tweet.is_empty())
+        .flat_map(|tweet| {
+            if tweet.chars().count() <= max_length {
+                vec![tweet]
+            } else {
+                split_tweet(&tweet, max_length)
+            }
+        })
+        .collect()
+}
+
+fn split_tweet(text: &str, max_length: usize) -> Vec<String> {
+    let mut tweets = Vec::new();
+    let mut current = String::new();
+
+    for (line_break, sentence) in sentences(text) {
+        let pieces = if sentence.chars().count() > max_length {
+            split_words(sentence, max_length)
+        } else {
+            vec![sentence.to_string()]
+        };
+
+        for (index, piece) in pieces.into_iter().enumerate() {
+            let separator = match (current.is_empty(), index) {
+                (true, _) => "",
+                (false, 0) => line_break,
+                (false, _) => " ",
+            };
+
+            if current.chars().count() + separator.len() + piece.chars().count() > max_length {
+                tweets.push(std::mem::take(&mut current));
+                current = piece;
+            } else {
+                current.push_str(separator);
+                current.push_str(&piece);
+            }
+        }
+    }
+
+    if !current.is_empty() {
+        tweets.push(current);
+    }
+
+    tweets
+}
+
+/// Splits the text into sentences, each with the separator it followed: a space, or the line
+/// breaks between them (at most one blank line) so paragraphs survive the split.
+fn sentences(text: &str) -> Vec<(&'static str, &str)> {
+    let mut sentences = Vec::new();
+    let mut newlines = 0;
+
+    for part in text.split_inclusive(['.', '!', '?', '\n']) {
+        let sentence = part.trim();
+        if sentence.is_empty() {
+            newlines += part.matches('\n').count();
+            continue;
+        }
+
+        newlines += part[..part.len() - part.trim_start().len()]
+            .matches('\n')
+            .count();
+        let separator = match newlines {
+            0 => " ",
+            1 => "\n",
+            _ => "\n\n",
+        };
+        sentences.push((separator, sentence));
+
+        newlines = part[part.trim_end().len()..].matches('\n').count();
+    }
+
+    sentences
+}
+
+fn split_words(text: &str, max_length: usize) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+
+    for word in text.split_whitespace() {
+        let chars: Vec<char> = word.chars().collect();
+
+        for chunk in chars.chunks(max_length) {
+            let chunk: String = chunk.iter().collect();
+            let separator = if current.is_empty() { "" } else { " " };
+
+            if current.chars().count() + separator.len() + chunk.chars().count() > max_length {
+                pieces.push(std::mem::take(&mut current));
+                current = chunk;
+            } else {
+                current.push_str(separator);
+                current.push_str(&chunk);
+            }
+        }
+    }
+
+    if !current.is_empty() {
+        pieces.push(current);
+    }
+
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tweets(tweets: &[&str]) -> Vec<String> {
+        tweets.iter().map(|tweet| tweet.to_string()).collect()
+    }
+
+    #[test]
+    fn keeps_short_tweets() {
+        assert_eq!(
+            enforce_max_length(tweets(&["  First.  ", "", "Second."]), 20),
+            tweets(&["First.", "Second."])
+        );
+    }
+
+    #[test]
+    fn splits_at_sentences() {
+        assert_eq!(
+            enforce_max_length(tweets(&["One sentence. Another one! A third?"]), 30),
+            tweets(&["One sentence. Another one!", "A third?"])
+        );
+    }
+
+    #[test]
+    fn keeps_line_breaks() {
+        assert_eq!(
+            enforce_max_length(
+                tweets(&["A list:\n- first\n- second\n\n\n\nThe end. Really the end."]),
+                40
+            ),
+            tweets(&["A list:\n- first\n- second\n\nThe end.", "Really the end."])
+        );
+    }
+
+    #[test]
+    fn splits_long_words() {
+        assert_eq!(
+            enforce_max_length(tweets(&["Visit abcdefghijklmnop now"]), 8),
+            tweets(&["Visit", "abcdefgh", "ijklmnop", "now"])
+        );
+    }
+
+    #[test]
+    fn counts_tweets() {
+        let thread = join(&tweets(&["One.", "Two.", "Three."]));
+
+        assert_eq!(tweet_count(&thread), 3);
+        assert_eq!(tweet_count("Just one."), 1);
+    }
+}
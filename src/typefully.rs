@@ -0,0 +1,322 @@
+use std::sync::OnceLock;
+
+use reqwest::{header::RETRY_AFTER, Method, RequestBuilder, Response, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+const DEFAULT_API_URL: &str = "https://api.typefully.com/v1/";
+
+#[derive(Debug, thiserror::Error)]
+pub enum TypefullyError {
+    #[error("Typefully rejected your API key. Please set a valid one with /settypefullykey.")]
+    Unauthorized,
+    #[error("Typefully is rate limiting requests. Please try again {}.", retry_hint(.retry_after))]
+    RateLimited { retry_after: Option<u64> },
+    #[error("Typefully rejected the draft: {0}")]
+    Validation(String),
+    #[error("Typefully responded with {status}: {message}")]
+    Api { status: StatusCode, message: String },
+    #[error("Typefully sent an unexpected response: {0}")]
+    InvalidResponse(reqwest::Error),
+    #[error("Could not reach Typefully: {0}")]
+    Network(#[from] reqwest::Error),
+}
+
+fn retry_hint(retry_after: &Option<u64>) -> String {
+    match retry_after {
+        Some(seconds) => format!("in {} seconds", seconds),
+        None => "later".to_string(),
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CreateDraft {
+    pub content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threadify: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub share: Option<bool>,
+    #[serde(rename = "schedule-date", skip_serializing_if = "Option::is_none")]
+    pub schedule_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_retweet_enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_plug_enabled: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Draft {
+    pub id: i64,
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub text_first_tweet: Option<String>,
+    #[serde(default)]
+    pub share_url: Option<String>,
+}
+
+/// Client for the Typefully v1 API, authenticated with a single user's key.
+#[derive(Clone)]
+pub struct TypefullyClient {
+    base_url: String,
+    api_key: String,
+    http: reqwest::Client,
+}
+
+impl TypefullyClient {
+    /// Creates a client for `TYPEFULLY_API_URL`, defaulting to the public API.
+    pub fn new(api_key: impl Into<String>) -> Self {
+        let base_url =
+            std::env::var("TYPEFULLY_API_URL").unwrap_or_else(|_| DEFAULT_API_URL.to_string());
+
+        Self::with_base_url(api_key, base_url)
+    }
+
+    pub fn with_base_url(api_key: impl Into<String>, base_url: impl Into<String>) -> Self {
+        static HTTP: OnceLock<reqwest::Client> = OnceLock::new();
+
+        let mut base_url = base_url.into();
+        if !base_url.ends_with('/') {
+            base_url.push('/');
+        }
+
+        Self {
+            base_url,
+            api_key: api_key.into(),
+            http: HTTP.get_or_init(reqwest::Client::new).clone(),
+        }
+    }
+
+    pub async fn create_draft(&self, draft: &CreateDraft) -> Result<Draft, TypefullyError> {
+        let request = self.request(Method::POST, "drafts/").json(draft);
+
+        self.send(request).await
+    }
+
+    /// Loads the user's notifications, which only succeeds with a valid key.
+    pub async fn notifications(&self) -> Result<Value, TypefullyError> {
+        let request = self.request(Method::GET, "notifications/");
+
+        self.send(request).await
+    }
+
+    pub async fn recently_published(&self) -> Result<Vec<Draft>, TypefullyError> {
+        let request = self.request(Method::GET, "drafts/recently-published/");
+
+        self.send(request).await
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        // The v1 API expects the key in `X-API-KEY`, prefixed with `Bearer` like an Authorization header.
+        self.http
+            .request(method, format!("{}{}", self.base_url, path))
+            .header("X-API-KEY", format!("Bearer {}", self.api_key))
+    }
+
+    async fn send<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<T, TypefullyError> {
+        let response = request.send().await?;
+
+        if response.status().is_success() {
+            return response
+                .json()
+                .await
+                .map_err(TypefullyError::InvalidResponse);
+        }
+
+        Err(Self::error_from(response).await)
+    }
+
+    async fn error_from(response: Response) -> TypefullyError {
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok());
+        let message = error_message(&response.text().await.unwrap_or_default());
+
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => TypefullyError::Unauthorized,
+            StatusCode::TOO_MANY_REQUESTS => TypefullyError::RateLimited { retry_after },
+            StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => {
+                TypefullyError::Validation(message)
+            }
+            status => TypefullyError::Api { status, message },
+        }
+    }
+}
+
+/// Extracts a readable message from an error body like `{"detail": "..."}` or
+/// `{"content": ["This field is required."]}`.
+fn error_message(body: &str) -> String {
+    let Ok(json) = serde_json::from_str::<Value>(body) else {
+        return body.chars().take(200).collect();
+    };
+
+    if let Some(detail) = json.get("detail").and_then(Value::as_str) {
+        return detail.to_string();
+    }
+
+    match json {
+        Value::Object(fields) => fields
+            .iter()
+            .map(|(field, errors)| match errors {
+                Value::Array(errors) => format!(
+                    "{}: {}",
+                    field,
+                    errors
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                ),
+                Value::String(error) => format!("{}: {}", field, error),
+                other => format!("{}: {}", field, other),
+            })
+            .collect::<Vec<_>>()
+            .join("; "),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use super::*;
+
+    /// Answers a single request with `status`, the extra `headers` and `body`, and returns the
+    /// API URL to reach it.
+    fn serve_once(status: &str, headers: &str, body: &str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let response = format!(
+            "HTTP/1.1 {}\r\n{}Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            headers,
+            body.len(),
+            body
+        );
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+
+            // Read the whole request, so closing the connection doesn't reset it
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+            let mut request_body = vec![0; content_length];
+            reader.read_exact(&mut request_body).unwrap();
+
+            reader.into_inner().write_all(response.as_bytes()).unwrap();
+        });
+
+        format!("http://{}/v1/", address)
+    }
+
+    async fn create_draft(
+        status: &str,
+        headers: &str,
+        body: &str,
+    ) -> Result<Draft, TypefullyError> {
+        let client = TypefullyClient::with_base_url("key", serve_once(status, headers, body));
+        let draft = CreateDraft {
+            content: "Hello".to_string(),
+            ..Default::default()
+        };
+
+        client.create_draft(&draft).await
+    }
+
+    #[tokio::test]
+    async fn reads_the_api_url_from_the_environment() {
+        let api_url = serve_once(
+            "200 OK",
+            "",
+            r#"{"id": 42, "share_url": "https://typefully.com/t/abc"}"#,
+        );
+        std::env::set_var("TYPEFULLY_API_URL", api_url);
+
+        let draft = TypefullyClient::new("key")
+            .create_draft(&CreateDraft::default())
+            .await
+            .unwrap();
+
+        assert_eq!(draft.id, 42);
+        assert_eq!(
+            draft.share_url.as_deref(),
+            Some("https://typefully.com/t/abc")
+        );
+    }
+
+    #[tokio::test]
+    async fn maps_statuses_to_errors() {
+        for status in ["401 Unauthorized", "403 Forbidden"] {
+            assert!(matches!(
+                create_draft(status, "", r#"{"detail": "Invalid key"}"#).await,
+                Err(TypefullyError::Unauthorized)
+            ));
+        }
+
+        assert!(matches!(
+            create_draft("429 Too Many Requests", "Retry-After: 30\r\n", "{}").await,
+            Err(TypefullyError::RateLimited {
+                retry_after: Some(30)
+            })
+        ));
+        assert!(matches!(
+            create_draft("429 Too Many Requests", "", "{}").await,
+            Err(TypefullyError::RateLimited { retry_after: None })
+        ));
+
+        for status in ["400 Bad Request", "422 Unprocessable Entity"] {
+            match create_draft(status, "", r#"{"content": ["This field is required."]}"#).await {
+                Err(TypefullyError::Validation(message)) => {
+                    assert_eq!(message, "content: This field is required.")
+                }
+                other => panic!("{}: {:?}", status, other),
+            }
+        }
+
+        match create_draft("500 Internal Server Error", "", "Oops").await {
+            Err(TypefullyError::Api { status, message }) => {
+                assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+                assert_eq!(message, "Oops");
+            }
+            other => panic!("{:?}", other),
+        }
+
+        assert!(matches!(
+            create_draft("200 OK", "", "not json").await,
+            Err(TypefullyError::InvalidResponse(_))
+        ));
+    }
+
+    #[test]
+    fn reads_error_messages() {
+        assert_eq!(error_message(r#"{"detail": "Not found."}"#), "Not found.");
+        assert_eq!(
+            error_message(r#"{"content": ["Too long.", "Empty."], "schedule-date": "Invalid."}"#),
+            "content: Too long. Empty.; schedule-date: Invalid."
+        );
+        assert_eq!(error_message(r#"{"threadify": true}"#), "threadify: true");
+        assert_eq!(error_message(r#"["Oops"]"#), r#"["Oops"]"#);
+        assert_eq!(error_message(&"x".repeat(300)), "x".repeat(200));
+    }
+}
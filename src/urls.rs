@@ -0,0 +1,255 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect, Url,
+};
+
+/// How long connecting to a user's server may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The servers users may point the bot at, e.g. for webhooks or their own AI provider.
+///
+/// Only public `https` hosts are accepted, so users can't make the bot reach the machine it
+/// runs on, its network or cloud metadata endpoints.
+pub struct UrlPolicy {
+    /// Hosts the operator allows. Any public host is allowed when empty.
+    allowed_hosts: Vec<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum UrlRejected {
+    #[error("That doesn't look like a valid URL.")]
+    Invalid,
+    #[error("Only https URLs are supported.")]
+    NotHttps,
+    #[error("{0} is not on the list of allowed hosts.")]
+    NotAllowed(String),
+    #[error("{0} could not be resolved.")]
+    Unresolvable(String),
+    #[error("{0} points to a private or local address.")]
+    NotPublic(String),
+}
+
+impl UrlPolicy {
+    /// Reads the comma separated `WEBHOOK_ALLOWED_HOSTS`.
+    pub fn webhooks() -> Self {
+        Self::from_env("WEBHOOK_ALLOWED_HOSTS")
+    }
+
+    /// Reads the comma separated `AI_BASE_URL_ALLOWED_HOSTS`.
+    pub fn ai_base_urls() -> Self {
+        Self::from_env("AI_BASE_URL_ALLOWED_HOSTS")
+    }
+
+    fn from_env(var: &str) -> Self {
+        Self {
+            allowed_hosts: std::env::var(var)
+                .unwrap_or_default()
+                .split(',')
+                .map(|host| host.trim().to_lowercase())
+                .filter(|host| !host.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Accepts `url` if it is an allowed `https` URL whose host only resolves to public
+    /// addresses.
+    pub async fn check(&self, url: &str) -> Result<Url, UrlRejected> {
+        let url = Url::parse(url).map_err(|_| UrlRejected::Invalid)?;
+        if url.scheme() != "https" {
+            return Err(UrlRejected::NotHttps);
+        }
+
+        let host = url.host_str().ok_or(UrlRejected::Invalid)?.to_lowercase();
+        if !self.allowed_hosts.is_empty() && !self.allowed_hosts.contains(&host) {
+            return Err(UrlRejected::NotAllowed(host));
+        }
+
+        let port = url.port_or_known_default().unwrap_or(443);
+        // IP addresses are never passed to the resolver, so they are checked here
+        let literal = host.trim_start_matches('[').trim_end_matches(']');
+        let addresses: Vec<SocketAddr> = match literal.parse::<IpAddr>() {
+            Ok(ip) => vec![SocketAddr::new(ip, port)],
+            Err(_) => tokio::net::lookup_host((host.as_str(), port))
+                .await
+                .map_err(|_| UrlRejected::Unresolvable(host.clone()))?
+                .collect(),
+        };
+
+        if addresses.is_empty() {
+            return Err(UrlRejected::Unresolvable(host));
+        }
+        if !addresses.iter().all(|address| is_public(address.ip())) {
+            return Err(UrlRejected::NotPublic(host));
+        }
+
+        Ok(url)
+    }
+}
+
+/// A client for servers users chose. It doesn't follow redirects and refuses to connect to
+/// hosts resolving to non-public addresses, so a checked host can't be swapped for an internal
+/// one later.
+pub fn public_client(timeout: Duration) -> reqwest::Client {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(timeout)
+        .redirect(redirect::Policy::none())
+        .dns_resolver(Arc::new(PublicResolver))
+        .build()
+        .unwrap_or_default()
+}
+
+/// Resolves host names, dropping every address that isn't public.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+
+        Box::pin(async move {
+            let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|address| is_public(address.ip()))
+                .collect();
+            if addresses.is_empty() {
+                return Err(UrlRejected::NotPublic(host).into());
+            }
+
+            let addresses: Addrs = Box::new(addresses.into_iter());
+            Ok(addresses)
+        })
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // "This network", shared address space, IETF protocol assignments, benchmarking and
+        // reserved ranges
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 192 && b == 0 && ip.octets()[2] == 0)
+        || (a == 198 && (18..20).contains(&b))
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local, link-local and documentation ranges
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allowed_hosts: &[&str]) -> UrlPolicy {
+        UrlPolicy {
+            allowed_hosts: allowed_hosts.iter().map(|host| host.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn recognizes_public_addresses() {
+        for ip in ["1.1.1.1", "8.8.8.8", "2606:4700:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00:ec2::254",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_unsafe_urls() {
+        let policy = policy(&[]);
+
+        assert!(matches!(
+            policy.check("not a url").await,
+            Err(UrlRejected::Invalid)
+        ));
+        assert!(matches!(
+            policy.check("http://1.1.1.1/hook").await,
+            Err(UrlRejected::NotHttps)
+        ));
+        for url in [
+            "https://127.0.0.1/hook",
+            "https://169.254.169.254/latest/meta-data",
+            "https://[::1]:8080/v1",
+            "https://[::ffff:10.0.0.1]/",
+            "https://localhost/hook",
+        ] {
+            assert!(
+                matches!(policy.check(url).await, Err(UrlRejected::NotPublic(_))),
+                "{}",
+                url
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn accepts_public_urls() {
+        assert_eq!(
+            policy(&[])
+                .check("https://1.1.1.1/hook")
+                .await
+                .unwrap()
+                .as_str(),
+            "https://1.1.1.1/hook"
+        );
+    }
+
+    #[tokio::test]
+    async fn enforces_the_allowlist() {
+        let policy = policy(&["1.1.1.1"]);
+
+        assert!(policy.check("https://1.1.1.1/hook").await.is_ok());
+        assert!(matches!(
+            policy.check("https://8.8.8.8/hook").await,
+            Err(UrlRejected::NotAllowed(_))
+        ));
+    }
+}
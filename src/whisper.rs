@@ -0,0 +1,137 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use log::{error, warn};
+use tokio::process::Command;
+
+use crate::{
+    media::convert_to_wav,
+    providers::{Transcriber, Transcript},
+};
+
+const DEFAULT_WHISPER_BIN: &str = "whisper-cli";
+
+/// Transcribes on this machine with whisper.cpp, so recordings never leave the server.
+#[derive(Debug, Clone)]
+pub struct LocalWhisper {
+    binary: PathBuf,
+    model_path: PathBuf,
+    threads: usize,
+}
+
+impl LocalWhisper {
+    /// Reads `WHISPER_MODEL_PATH`, `WHISPER_THREADS` and `WHISPER_BIN`. Returns `None` if no
+    /// model is configured, and fails if the model or the binary can't be found, so a broken
+    /// setup is noticed at startup instead of on the first note.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Some(model_path) = std::env::var("WHISPER_MODEL_PATH")
+            .ok()
+            .filter(|path| !path.is_empty())
+        else {
+            warn!("WHISPER_MODEL_PATH is not set, notes are transcribed by the AI provider");
+            return Ok(None);
+        };
+
+        let model_path = PathBuf::from(model_path);
+        if !model_path.is_file() {
+            bail!(
+                "The whisper.cpp model {} does not exist, check WHISPER_MODEL_PATH",
+                model_path.display()
+            );
+        }
+
+        let threads = std::env::var("WHISPER_THREADS")
+            .ok()
+            .and_then(|threads| threads.parse().ok())
+            .unwrap_or_else(|| {
+                std::thread::available_parallelism()
+                    .map(|threads| threads.get())
+                    .unwrap_or(4)
+            });
+
+        let binary =
+            PathBuf::from(std::env::var("WHISPER_BIN").unwrap_or(DEFAULT_WHISPER_BIN.to_string()));
+        std::process::Command::new(&binary)
+            .arg("--help")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .with_context(|| {
+                format!(
+                    "Failed to run {}, install whisper.cpp or set WHISPER_BIN",
+                    binary.display()
+                )
+            })?;
+
+        Ok(Some(Self {
+            binary,
+            model_path,
+            threads,
+        }))
+    }
+}
+
+#[async_trait]
+impl Transcriber for LocalWhisper {
+    async fn transcribe(&self, path: &Path, language: Option<&str>) -> anyhow::Result<Transcript> {
+        let wav_path = path.with_extension("wav");
+        convert_to_wav(path, &wav_path).await?;
+
+        let result = Command::new(&self.binary)
+            .arg("--model")
+            .arg(&self.model_path)
+            .args(["--threads", &self.threads.to_string()])
+            .args(["--language", language.unwrap_or("auto")])
+            .arg("--no-timestamps")
+            .arg("--file")
+            .arg(&wav_path)
+            .output()
+            .await;
+
+        if let Err(e) = tokio::fs::remove_file(&wav_path).await {
+            error!("Failed to clean up converted audio file: {}", e);
+        }
+
+        let output = result.with_context(|| {
+            format!(
+                "Failed to run {}, is whisper.cpp installed?",
+                self.binary.display()
+            )
+        })?;
+
+        if !output.status.success() {
+            bail!(
+                "whisper.cpp failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+                    .lines()
+                    .last()
+                    .unwrap_or_default()
+            );
+        }
+
+        let text = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Ok(Transcript {
+            text,
+            language: detected_language(&String::from_utf8_lossy(&output.stderr))
+                .or(language.map(str::to_string)),
+        })
+    }
+}
+
+/// whisper.cpp logs e.g. "auto-detected language: de (p = 0.97)" when detecting the language.
+fn detected_language(log: &str) -> Option<String> {
+    log.lines().find_map(|line| {
+        let (_, detected) = line.split_once("auto-detected language: ")?;
+        detected.split_whitespace().next().map(str::to_string)
+    })
+}